@@ -1,13 +1,31 @@
 #![allow(unused)]
 
-use std::{
-    cell::UnsafeCell,
-    sync::{
-        atomic::{AtomicBool, AtomicUsize, Ordering},
-        Mutex,
-    },
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Mutex,
 };
 
+// With `--cfg loom` the mutex is built on loom's types instead, so the model at the bottom of the file
+// can try every interleaving of `with_lock`: RUSTFLAGS="--cfg loom" cargo test -p atomics --release
+#[cfg(loom)]
+use loom::{cell::UnsafeCell, sync::atomic::AtomicBool, thread::yield_now as spin};
+#[cfg(not(loom))]
+use std::{hint::spin_loop as spin, sync::atomic::AtomicBool};
+
+// std's UnsafeCell with the interface of loom's, so `with_lock` is the same code in both builds
+#[cfg(not(loom))]
+struct UnsafeCell<T>(std::cell::UnsafeCell<T>);
+
+#[cfg(not(loom))]
+impl<T> UnsafeCell<T> {
+    fn new(t: T) -> Self {
+        Self(std::cell::UnsafeCell::new(t))
+    }
+    fn with_mut<R>(&self, f: impl FnOnce(*mut T) -> R) -> R {
+        f(self.0.get())
+    }
+}
+
 const LOCKED: bool = true;
 const UNLOCKED: bool = false;
 pub struct MyMutex<T> {
@@ -30,9 +48,12 @@ impl<T> MyMutex<T> {
             .locked
             .compare_exchange_weak(UNLOCKED, LOCKED, Ordering::Acquire, Ordering::Acquire)
             .is_err()
-        {}
+        {
+            // Loom only moves on to another thread when this one yields
+            spin();
+        }
         // Safety: We hold the lock therefore we can create a mutable reference.
-        let ret = f(unsafe { &mut *self.v.get() });
+        let ret = self.v.with_mut(|v| f(unsafe { &mut *v }));
         // Change to `Release` to ensure the next thread that reads will see this operation.
         self.locked.store(UNLOCKED, Ordering::Release);
         ret
@@ -93,6 +114,7 @@ fn main() {
     println!("{z}");
 }
 
+#[cfg(not(loom))]
 #[test]
 fn mutex_test() {
     let l: &'static _ = Box::leak(Box::new(MyMutex::new(0)));
@@ -142,3 +164,23 @@ fn too_relaxed() {
     // With Ordering::Relaxed there are almost no guarantess of the order of reading / writing.
     // D can happen before A and B can happen before C.
 }
+
+#[cfg(loom)]
+#[test]
+fn loom_mutex_exclusion() {
+    use loom::{sync::Arc, thread};
+    // Loom panics if the two closures ever touch `v` at the same time, or if one does not see the other's write
+    loom::model(|| {
+        let l = Arc::new(MyMutex::new(0));
+        let handles: Vec<_> = (0..2)
+            .map(|_| {
+                let l = Arc::clone(&l);
+                thread::spawn(move || l.with_lock(|v| *v += 1))
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        assert_eq!(l.with_lock(|v| *v), 2);
+    });
+}
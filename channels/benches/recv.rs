@@ -0,0 +1,94 @@
+// Throughput of the batching Receiver against the old one-lock-per-message receive path.
+// Run with `cargo bench -p channels`.
+use std::collections::VecDeque;
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const MESSAGES: u64 = 1_000_000;
+
+// The receive path before the Receiver got its buffer: one lock per value.
+struct Unbuffered {
+    queue: Mutex<(VecDeque<u64>, bool)>, // (queue, sender alive)
+    available: Condvar,
+}
+
+fn unbuffered() -> Duration {
+    let shared = Arc::new(Unbuffered {
+        queue: Mutex::new((VecDeque::new(), true)),
+        available: Condvar::new(),
+    });
+    let tx = Arc::clone(&shared);
+    let start = Instant::now();
+    let producer = thread::spawn(move || {
+        for i in 0..MESSAGES {
+            tx.queue.lock().unwrap().0.push_back(i);
+            tx.available.notify_one();
+        }
+        tx.queue.lock().unwrap().1 = false;
+        tx.available.notify_one();
+    });
+    let mut sum = 0;
+    let mut inner = shared.queue.lock().unwrap();
+    loop {
+        match inner.0.pop_front() {
+            Some(i) => {
+                sum += i;
+                drop(inner);
+                inner = shared.queue.lock().unwrap();
+            }
+            None if !inner.1 => break,
+            None => inner = shared.available.wait(inner).unwrap(),
+        }
+    }
+    drop(inner);
+    producer.join().unwrap();
+    assert_eq!(sum, MESSAGES * (MESSAGES - 1) / 2);
+    start.elapsed()
+}
+
+fn recv() -> Duration {
+    let (mut tx, mut rx) = channels::channel();
+    let start = Instant::now();
+    let producer = thread::spawn(move || {
+        for i in 0..MESSAGES {
+            tx.send(i).unwrap();
+        }
+    });
+    let mut sum = 0;
+    while let Some(i) = rx.recv() {
+        sum += i;
+    }
+    producer.join().unwrap();
+    assert_eq!(sum, MESSAGES * (MESSAGES - 1) / 2);
+    start.elapsed()
+}
+
+fn recv_many() -> Duration {
+    let (mut tx, mut rx) = channels::channel();
+    let start = Instant::now();
+    let producer = thread::spawn(move || {
+        for i in 0..MESSAGES {
+            tx.send(i).unwrap();
+        }
+    });
+    let mut sum = 0;
+    let mut batch = Vec::with_capacity(1024);
+    while rx.recv_many(&mut batch, 1024) != 0 {
+        sum += batch.drain(..).sum::<u64>();
+    }
+    producer.join().unwrap();
+    assert_eq!(sum, MESSAGES * (MESSAGES - 1) / 2);
+    start.elapsed()
+}
+
+fn report(name: &str, elapsed: Duration) {
+    let per_msg = elapsed.as_nanos() as f64 / MESSAGES as f64;
+    println!("{name:<12} {elapsed:>12.2?} {per_msg:>8.1} ns/msg");
+}
+
+fn main() {
+    report("unbuffered", unbuffered());
+    report("recv", recv());
+    report("recv_many", recv_many());
+}
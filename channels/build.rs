@@ -0,0 +1,8 @@
+// `--cfg loom` from RUSTFLAGS reaches rustc but not rustdoc. Passing it on from here means the
+// doc examples see it as well, so the ones that need the real Mutex can be skipped under loom.
+fn main() {
+    println!("cargo:rerun-if-env-changed=CARGO_CFG_LOOM");
+    if std::env::var_os("CARGO_CFG_LOOM").is_some() {
+        println!("cargo:rustc-cfg=loom");
+    }
+}
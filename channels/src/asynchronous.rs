@@ -0,0 +1,293 @@
+// Async halves of the same channel. Instead of parking the thread on a Condvar they leave the
+// task's Waker in `Inner` (`wakers` for receivers, `space_wakers` for senders) and return Pending.
+// Both sides share `Shared` with the blocking halves, so a blocking Sender on a plain thread can
+// feed an async Receiver in a runtime and the other way around (see `From` and `into_sync`).
+use crate::select::Selectable;
+use crate::{register, wake_all, SendError, TryRecvError};
+use futures_core::Stream;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+pub struct Sender<T> {
+    inner: crate::Sender<T>,
+}
+
+impl<T> Sender<T> {
+    /// Resolves once the value is queued (or, for a rendezvous channel, taken).
+    pub fn send(&mut self, t: T) -> SendFuture<'_, T> {
+        SendFuture {
+            tx: &self.inner,
+            value: Some(t),
+            ticket: None,
+        }
+    }
+
+    pub fn into_sync(self) -> crate::Sender<T> {
+        self.inner
+    }
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        Sender {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<T> From<crate::Sender<T>> for Sender<T> {
+    fn from(inner: crate::Sender<T>) -> Self {
+        Sender { inner }
+    }
+}
+
+pub struct SendFuture<'a, T> {
+    tx: &'a crate::Sender<T>,
+    value: Option<T>,
+    ticket: Option<usize>, // Rendezvous only: set once our value is queued, see `Sender::send`
+}
+
+// We never hand out a `Pin<&mut T>`, the value is just moved into the queue
+impl<T> Unpin for SendFuture<'_, T> {}
+
+impl<T> Future for SendFuture<'_, T> {
+    type Output = Result<(), SendError<T>>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        let shared = &*this.tx.shared;
+        let mut inner = shared.lock();
+
+        if let Some(ticket) = this.ticket {
+            // Rendezvous: our value is queued, wait for a receiver to take it
+            if inner.taken >= ticket {
+                return Poll::Ready(Ok(()));
+            }
+            if inner.receivers == 0 {
+                // Our value is the only one in the queue, take it back
+                let t = inner
+                    .queue
+                    .pop_back()
+                    .expect("rendezvous value is still queued");
+                return Poll::Ready(Err(SendError(t)));
+            }
+            register(&mut inner.space_wakers, cx.waker());
+            return Poll::Pending;
+        }
+
+        let t = this
+            .value
+            .take()
+            .expect("SendFuture polled after completion");
+        if inner.receivers == 0 {
+            return Poll::Ready(Err(SendError(t)));
+        }
+        if let Some(capacity) = inner.capacity {
+            if inner.queue.len() >= capacity.max(1) {
+                // Full: come back when a receiver takes something (or the last one leaves)
+                this.value = Some(t);
+                register(&mut inner.space_wakers, cx.waker());
+                return Poll::Pending;
+            }
+        }
+        shared.push(&mut inner, t);
+        let wakers = std::mem::take(&mut inner.wakers);
+        let poll = if inner.capacity == Some(0) {
+            this.ticket = Some(inner.taken + inner.queue.len());
+            register(&mut inner.space_wakers, cx.waker());
+            Poll::Pending
+        } else {
+            Poll::Ready(Ok(()))
+        };
+        drop(inner);
+        wake_all(wakers);
+        shared.available.notify_one(); // There may be a blocking Receiver on the other end
+        poll
+    }
+}
+
+pub struct Receiver<T> {
+    inner: crate::Receiver<T>,
+}
+
+// We never hand out a `Pin<&mut T>`, values are only moved out of the queue
+impl<T> Unpin for Receiver<T> {}
+
+impl<T> Receiver<T> {
+    /// Resolves to `None` once every Sender is gone and the queue is drained.
+    pub fn recv(&mut self) -> RecvFuture<'_, T> {
+        RecvFuture { rx: self }
+    }
+
+    pub fn try_recv(&mut self) -> Result<T, TryRecvError> {
+        self.inner.try_recv()
+    }
+
+    pub fn into_sync(self) -> crate::Receiver<T> {
+        self.inner
+    }
+
+    fn poll_recv(&mut self, cx: &mut Context<'_>) -> Poll<Option<T>> {
+        loop {
+            match self.inner.try_recv() {
+                Ok(t) => return Poll::Ready(Some(t)),
+                Err(TryRecvError::Disconnected) | Err(TryRecvError::Panicked) => {
+                    return Poll::Ready(None)
+                }
+                Err(TryRecvError::Empty) => {
+                    // Registering re-checks under the lock: if a value showed up in between, go get it
+                    if !self.inner.register(cx.waker()) {
+                        return Poll::Pending;
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl<T> Clone for Receiver<T> {
+    fn clone(&self) -> Self {
+        Receiver {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<T> From<crate::Receiver<T>> for Receiver<T> {
+    fn from(inner: crate::Receiver<T>) -> Self {
+        Receiver { inner }
+    }
+}
+
+impl<T> Stream for Receiver<T> {
+    type Item = T;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.get_mut().poll_recv(cx)
+    }
+}
+
+pub struct RecvFuture<'a, T> {
+    rx: &'a mut Receiver<T>,
+}
+
+impl<T> Future for RecvFuture<'_, T> {
+    type Output = Option<T>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        self.get_mut().rx.poll_recv(cx)
+    }
+}
+
+pub fn channel<T>() -> (Sender<T>, Receiver<T>) {
+    let (tx, rx) = crate::channel();
+    (tx.into(), rx.into())
+}
+
+/// Like `crate::sync_channel`, but a full channel makes `send` return Pending instead of blocking.
+pub fn sync_channel<T>(capacity: usize) -> (Sender<T>, Receiver<T>) {
+    let (tx, rx) = crate::sync_channel(capacity);
+    (tx.into(), rx.into())
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use super::*;
+    use futures::executor::block_on;
+    use futures::StreamExt;
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn it_works() {
+        let (mut tx, mut rx) = channel();
+        block_on(async {
+            tx.send(42).await.unwrap();
+            assert_eq!(rx.recv().await, Some(42));
+        });
+    }
+
+    #[test]
+    fn closed() {
+        let (tx, mut rx) = channel::<()>();
+        drop(tx);
+        assert_eq!(block_on(rx.recv()), None);
+    }
+
+    #[test]
+    fn closed_rx() {
+        let (mut tx, rx) = channel();
+        drop(rx);
+        assert_eq!(block_on(tx.send(42)), Err(SendError(42)));
+    }
+
+    #[test]
+    fn stream() {
+        let (mut tx, rx) = channel();
+        block_on(async {
+            for i in 0..3 {
+                tx.send(i).await.unwrap();
+            }
+            drop(tx);
+            assert_eq!(rx.collect::<Vec<_>>().await, vec![0, 1, 2]);
+        });
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn sync_sender_feeds_async_receiver() {
+        let (mut tx, rx) = crate::channel();
+        let mut rx = Receiver::from(rx);
+        let t = thread::spawn(move || {
+            for i in 0..100 {
+                thread::sleep(Duration::from_micros(100));
+                tx.send(i).unwrap();
+            }
+        });
+        let mut got = Vec::new();
+        while let Some(i) = rx.recv().await {
+            got.push(i);
+        }
+        assert_eq!(got, (0..100).collect::<Vec<_>>());
+        t.join().unwrap();
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn async_sender_feeds_sync_receiver() {
+        let (mut tx, rx) = sync_channel(1);
+        let mut rx = rx.into_sync();
+        let t = thread::spawn(move || rx.by_ref().collect::<Vec<_>>());
+        for i in 0..100 {
+            tx.send(i).await.unwrap();
+        }
+        drop(tx);
+        assert_eq!(t.join().unwrap(), (0..100).collect::<Vec<_>>());
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn bounded_send_waits_for_room() {
+        let (mut tx, mut rx) = sync_channel(2);
+        let producer = tokio::spawn(async move {
+            for i in 0..10 {
+                tx.send(i).await.unwrap();
+            }
+        });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(!producer.is_finished()); // Only 2 fit
+        for i in 0..10 {
+            assert_eq!(rx.recv().await, Some(i));
+        }
+        producer.await.unwrap();
+        assert_eq!(rx.recv().await, None);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn rendezvous() {
+        let (mut tx, mut rx) = sync_channel(0);
+        let producer = tokio::spawn(async move { tx.send(42).await });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(!producer.is_finished()); // Queued, but nobody took it yet
+        assert_eq!(rx.recv().await, Some(42));
+        assert_eq!(producer.await.unwrap(), Ok(()));
+    }
+}
@@ -0,0 +1,286 @@
+// Every receiver sees every value.
+// Values live in a bounded ring and each Receiver keeps its own cursor (the sequence number of the
+// next value it wants). Sending never blocks: when the ring is full the oldest value is overwritten,
+// and a Receiver whose cursor pointed at it finds out through `Lagged`.
+use crate::sync::{self, Arc, Condvar, Mutex};
+use crate::SendError;
+use std::collections::VecDeque;
+use std::fmt;
+
+pub struct Sender<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> Sender<T> {
+    /// Fails and hands the value back if there is no Receiver to see it.
+    pub fn send(&mut self, t: T) -> Result<(), SendError<T>> {
+        let mut inner = sync::lock(&self.shared.inner);
+        if inner.receivers == 0 {
+            return Err(SendError(t));
+        }
+        if inner.ring.len() == inner.capacity {
+            // Overwrite the oldest value, slow receivers will get `Lagged`
+            inner.ring.pop_front();
+            inner.head += 1;
+        }
+        inner.ring.push_back(t);
+        drop(inner);
+        // Unlike the other channels every receiver wants this value
+        self.shared.available.notify_all();
+        Ok(())
+    }
+
+    /// A new Receiver that sees every value sent from now on.
+    pub fn subscribe(&self) -> Receiver<T> {
+        let mut inner = sync::lock(&self.shared.inner);
+        inner.receivers += 1;
+        let next = inner.head + inner.ring.len() as u64;
+        drop(inner);
+        Receiver {
+            shared: Arc::clone(&self.shared),
+            next,
+        }
+    }
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        let mut inner = sync::lock(&self.shared.inner);
+        inner.senders += 1;
+        drop(inner);
+        Sender {
+            shared: Arc::clone(&self.shared),
+        }
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        let mut inner = sync::lock(&self.shared.inner);
+        inner.senders -= 1;
+        let was_last = inner.senders == 0;
+        drop(inner);
+        if was_last {
+            self.shared.available.notify_all();
+        }
+    }
+}
+
+pub struct Receiver<T> {
+    shared: Arc<Shared<T>>,
+    next: u64, // Sequence number of the next value this Receiver wants
+}
+
+impl<T: Clone> Receiver<T> {
+    pub fn recv(&mut self) -> Result<T, RecvError> {
+        let mut inner = sync::lock(&self.shared.inner);
+        loop {
+            match take(&mut self.next, &inner) {
+                Err(TryRecvError::Empty) => {
+                    inner = sync::wait(&self.shared.available, inner);
+                }
+                Err(TryRecvError::Lagged(n)) => return Err(RecvError::Lagged(n)),
+                Err(TryRecvError::Disconnected) => return Err(RecvError::Disconnected),
+                Ok(t) => return Ok(t),
+            }
+        }
+    }
+
+    pub fn try_recv(&mut self) -> Result<T, TryRecvError> {
+        let inner = sync::lock(&self.shared.inner);
+        take(&mut self.next, &inner)
+    }
+}
+
+// Clones the value at `next` (the other receivers still need it) and moves the cursor past it
+fn take<T: Clone>(next: &mut u64, inner: &Inner<T>) -> Result<T, TryRecvError> {
+    if *next < inner.head {
+        // What we wanted was overwritten, skip to the oldest value still around
+        let missed = inner.head - *next;
+        *next = inner.head;
+        return Err(TryRecvError::Lagged(missed));
+    }
+    match inner.ring.get((*next - inner.head) as usize) {
+        Some(t) => {
+            *next += 1;
+            Ok(t.clone())
+        }
+        None if inner.senders == 0 => Err(TryRecvError::Disconnected),
+        None => Err(TryRecvError::Empty),
+    }
+}
+
+// The clone starts at the same position as the original.
+impl<T> Clone for Receiver<T> {
+    fn clone(&self) -> Self {
+        let mut inner = sync::lock(&self.shared.inner);
+        inner.receivers += 1;
+        drop(inner);
+        Receiver {
+            shared: Arc::clone(&self.shared),
+            next: self.next,
+        }
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        let mut inner = sync::lock(&self.shared.inner);
+        inner.receivers -= 1;
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum RecvError {
+    Lagged(u64), // The Receiver fell behind and this many values were overwritten before it saw them
+    Disconnected,
+}
+
+impl fmt::Display for RecvError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RecvError::Lagged(n) => write!(f, "receiver lagged behind by {} values", n),
+            RecvError::Disconnected => f.write_str("channel is empty and disconnected"),
+        }
+    }
+}
+
+impl std::error::Error for RecvError {}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum TryRecvError {
+    Empty,
+    Lagged(u64),
+    Disconnected,
+}
+
+impl fmt::Display for TryRecvError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TryRecvError::Empty => f.write_str("receiving on an empty channel"),
+            TryRecvError::Lagged(n) => write!(f, "receiver lagged behind by {} values", n),
+            TryRecvError::Disconnected => f.write_str("channel is empty and disconnected"),
+        }
+    }
+}
+
+impl std::error::Error for TryRecvError {}
+
+struct Inner<T> {
+    ring: VecDeque<T>,
+    head: u64, // Sequence number of `ring[0]`
+    capacity: usize,
+    senders: usize,
+    receivers: usize,
+}
+
+struct Shared<T> {
+    inner: Mutex<Inner<T>>,
+    available: Condvar,
+}
+
+/// Keeps the last `capacity` values around for receivers that are behind.
+pub fn channel<T>(capacity: usize) -> (Sender<T>, Receiver<T>) {
+    assert!(
+        capacity > 0,
+        "broadcast channel needs room for at least one value"
+    );
+    let inner = Inner {
+        ring: VecDeque::with_capacity(capacity),
+        head: 0,
+        capacity,
+        senders: 1,
+        receivers: 1,
+    };
+    let shared = Arc::new(Shared {
+        inner: Mutex::new(inner),
+        available: Condvar::new(),
+    });
+    (
+        Sender {
+            shared: Arc::clone(&shared),
+        },
+        Receiver { shared, next: 0 },
+    )
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn every_receiver_sees_every_value() {
+        let (mut tx, mut rx1) = channel(4);
+        let mut rx2 = rx1.clone();
+        tx.send(1).unwrap();
+        tx.send(2).unwrap();
+        drop(tx);
+        for rx in [&mut rx1, &mut rx2].iter_mut() {
+            assert_eq!(rx.recv(), Ok(1));
+            assert_eq!(rx.recv(), Ok(2));
+            assert_eq!(rx.recv(), Err(RecvError::Disconnected));
+        }
+    }
+
+    #[test]
+    fn subscribe_mid_stream() {
+        let (mut tx, mut rx1) = channel(4);
+        tx.send(1).unwrap();
+        let mut rx2 = tx.subscribe();
+        tx.send(2).unwrap();
+        assert_eq!(rx1.try_recv(), Ok(1));
+        assert_eq!(rx1.try_recv(), Ok(2));
+        // Only sees what was sent after it subscribed
+        assert_eq!(rx2.try_recv(), Ok(2));
+        assert_eq!(rx2.try_recv(), Err(TryRecvError::Empty));
+    }
+
+    #[test]
+    fn lagged() {
+        let (mut tx, mut rx) = channel(2);
+        for i in 0..5 {
+            tx.send(i).unwrap();
+        }
+        // 0, 1 and 2 were overwritten
+        assert_eq!(rx.recv(), Err(RecvError::Lagged(3)));
+        assert_eq!(rx.recv(), Ok(3));
+        assert_eq!(rx.recv(), Ok(4));
+    }
+
+    #[test]
+    fn closed_rx() {
+        let (mut tx, rx) = channel(1);
+        drop(rx);
+        assert_eq!(tx.send(42), Err(SendError(42)));
+        // A new subscriber makes sending possible again
+        let mut rx = tx.subscribe();
+        tx.send(43).unwrap();
+        assert_eq!(rx.recv(), Ok(43));
+    }
+
+    #[test]
+    fn recv_blocks_until_send() {
+        let (mut tx, rx) = channel(8);
+        let receivers: Vec<_> = (0..4)
+            .map(|_| {
+                let mut rx = rx.clone();
+                thread::spawn(move || {
+                    let mut got = Vec::new();
+                    while let Ok(i) = rx.recv() {
+                        got.push(i);
+                    }
+                    got
+                })
+            })
+            .collect();
+        drop(rx);
+        for i in 0..5 {
+            tx.send(i).unwrap();
+        }
+        drop(tx);
+        for r in receivers {
+            assert_eq!(r.join().unwrap(), vec![0, 1, 2, 3, 4]);
+        }
+    }
+}
@@ -0,0 +1,41 @@
+// Turning values into bytes and back, for channels whose values leave the process memory
+// (`spill` writes them to a file, `ipc` to a socket). The channel does the framing, a codec only
+// handles one value.
+use std::io;
+
+pub trait Codec<T> {
+    /// Appends the encoding of `value` to `buf`.
+    fn encode(&self, value: &T, buf: &mut Vec<u8>) -> io::Result<()>;
+    /// `buf` is exactly what one `encode` call appended.
+    fn decode(&self, buf: &[u8]) -> io::Result<T>;
+}
+
+/// Raw bytes, as they are.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Bytes;
+
+impl Codec<Vec<u8>> for Bytes {
+    fn encode(&self, value: &Vec<u8>, buf: &mut Vec<u8>) -> io::Result<()> {
+        buf.extend_from_slice(value);
+        Ok(())
+    }
+
+    fn decode(&self, buf: &[u8]) -> io::Result<Vec<u8>> {
+        Ok(buf.to_vec())
+    }
+}
+
+/// UTF-8 strings.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Utf8;
+
+impl Codec<String> for Utf8 {
+    fn encode(&self, value: &String, buf: &mut Vec<u8>) -> io::Result<()> {
+        buf.extend_from_slice(value.as_bytes());
+        Ok(())
+    }
+
+    fn decode(&self, buf: &[u8]) -> io::Result<String> {
+        String::from_utf8(buf.to_vec()).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+}
@@ -0,0 +1,289 @@
+// A channel between two processes on the same machine.
+// The values are encoded with a `Codec` and sent over a Unix domain socket, every one as a frame:
+// its length (u32, little endian) followed by the bytes. There are no shared counters to tell the
+// halves apart, the socket does it: dropping the Sender closes it, which the Receiver reads as EOF,
+// and writing to a socket whose Receiver is gone fails with `BrokenPipe`.
+// The length prefix comes from another process, so the Receiver does not trust it: a frame longer
+// than its maximum is an error instead of an allocation of whatever size the prefix says.
+// Nothing marks where a frame starts, so once one was cut off or too large to read the Receiver
+// can't find the next one and fails from then on.
+use crate::codec::Codec;
+use std::convert::TryFrom;
+use std::fmt;
+use std::io::{self, BufReader, Read, Write};
+use std::marker::PhantomData;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::Path;
+
+/// The largest frame a Receiver accepts unless told otherwise (see `Receiver::set_max_frame`).
+pub const DEFAULT_MAX_FRAME: usize = 16 << 20;
+
+/// Not `Clone`: a large frame can take more than one write, and two clones writing at the same
+/// time would mix their frames up.
+pub struct Sender<T, C> {
+    stream: UnixStream,
+    codec: C,
+    frame: Vec<u8>, // Reused between sends
+    _value: PhantomData<fn(T)>,
+}
+
+impl<T, C: Codec<T>> Sender<T, C> {
+    pub fn new(stream: UnixStream, codec: C) -> Self {
+        Sender {
+            stream,
+            codec,
+            frame: Vec::new(),
+            _value: PhantomData,
+        }
+    }
+
+    /// Connects to a Receiver waiting in `Receiver::accept`.
+    pub fn connect(path: impl AsRef<Path>, codec: C) -> io::Result<Self> {
+        Ok(Self::new(UnixStream::connect(path)?, codec))
+    }
+
+    /// Fails and hands the value back once the Receiver is gone.
+    pub fn send(&mut self, t: T) -> Result<(), SendError<T>> {
+        self.frame.clear();
+        self.frame.extend_from_slice(&[0; 4]); // Room for the length prefix
+        if let Err(e) = self.codec.encode(&t, &mut self.frame) {
+            return Err(SendError::Io(t, e));
+        }
+        let len = match u32::try_from(self.frame.len() - 4) {
+            Ok(len) => len,
+            Err(_) => {
+                let e = io::Error::new(io::ErrorKind::InvalidInput, "value too large for a frame");
+                return Err(SendError::Io(t, e));
+            }
+        };
+        self.frame[..4].copy_from_slice(&len.to_le_bytes());
+        // The whole frame in one call, so the Receiver never sees half a length prefix from us
+        match self.stream.write_all(&self.frame) {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == io::ErrorKind::BrokenPipe => Err(SendError::Disconnected(t)),
+            Err(e) => Err(SendError::Io(t, e)),
+        }
+    }
+}
+
+pub struct Receiver<T, C> {
+    stream: BufReader<UnixStream>,
+    codec: C,
+    frame: Vec<u8>,
+    max_frame: usize,
+    failed: bool, // Stopped in the middle of a frame, see the top of this file
+    _value: PhantomData<fn() -> T>,
+}
+
+impl<T, C: Codec<T>> Receiver<T, C> {
+    pub fn new(stream: UnixStream, codec: C) -> Self {
+        Receiver {
+            stream: BufReader::new(stream),
+            codec,
+            frame: Vec::new(),
+            max_frame: DEFAULT_MAX_FRAME,
+            failed: false,
+            _value: PhantomData,
+        }
+    }
+
+    /// Frames longer than `max` bytes (not counting the length prefix) make `recv` fail.
+    pub fn set_max_frame(&mut self, max: usize) {
+        self.max_frame = max;
+    }
+
+    /// Waits for one Sender to connect to `listener`.
+    pub fn accept(listener: &UnixListener, codec: C) -> io::Result<Self> {
+        let (stream, _) = listener.accept()?;
+        Ok(Self::new(stream, codec))
+    }
+
+    /// `Ok(None)` once the Sender is gone and every value it sent was received,
+    /// like `crate::Receiver::recv`. A frame that was cut off is an error, and so is one over the
+    /// maximum size (that one is not read). After either, every later call fails as well.
+    pub fn recv(&mut self) -> io::Result<Option<T>> {
+        if self.failed {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "an earlier frame was cut off or too large",
+            ));
+        }
+        let mut len = [0; 4];
+        // EOF before the first byte of a frame is the Sender hanging up, anywhere else it is an error
+        loop {
+            match self.stream.read(&mut len[..1]) {
+                Ok(0) => return Ok(None),
+                Ok(_) => break,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            }
+        }
+        // From here on an error leaves us somewhere inside the frame
+        if let Err(e) = self.read_frame(len) {
+            self.failed = true;
+            return Err(e);
+        }
+        self.codec.decode(&self.frame).map(Some)
+    }
+
+    // Reads the rest of a frame into `frame`, after the first byte of its length prefix
+    fn read_frame(&mut self, mut len: [u8; 4]) -> io::Result<()> {
+        self.stream.read_exact(&mut len[1..])?;
+        let len = u32::from_le_bytes(len) as usize;
+        if len > self.max_frame {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "frame of {} bytes is over the maximum of {}",
+                    len, self.max_frame
+                ),
+            ));
+        }
+        self.frame.resize(len, 0);
+        self.stream.read_exact(&mut self.frame)
+    }
+}
+
+pub enum SendError<T> {
+    Disconnected(T),  // The Receiver is gone
+    Io(T, io::Error), // Encoding the value or writing it to the socket failed
+}
+
+impl<T> SendError<T> {
+    pub fn into_inner(self) -> T {
+        match self {
+            SendError::Disconnected(t) | SendError::Io(t, _) => t,
+        }
+    }
+}
+
+impl<T> fmt::Debug for SendError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SendError::Disconnected(_) => f.write_str("Disconnected(..)"),
+            SendError::Io(_, e) => f.debug_tuple("Io").field(&"..").field(e).finish(),
+        }
+    }
+}
+
+impl<T> fmt::Display for SendError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SendError::Disconnected(_) => f.write_str("sending on a channel with no receiver"),
+            SendError::Io(_, e) => write!(f, "sending over the socket failed: {}", e),
+        }
+    }
+}
+
+impl<T> std::error::Error for SendError<T> {}
+
+/// Both halves over a connected socket pair, for a parent process to keep one and hand the other
+/// to a child (or for tests).
+pub fn channel<T, C: Codec<T> + Clone>(codec: C) -> io::Result<(Sender<T, C>, Receiver<T, C>)> {
+    let (tx, rx) = UnixStream::pair()?;
+    Ok((Sender::new(tx, codec.clone()), Receiver::new(rx, codec)))
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use super::*;
+    use crate::codec::{Bytes, Utf8};
+    use std::thread;
+
+    #[test]
+    fn it_works() {
+        let (mut tx, mut rx) = channel(Utf8).unwrap();
+        tx.send("hello".to_string()).unwrap();
+        tx.send(String::new()).unwrap();
+        assert_eq!(rx.recv().unwrap(), Some("hello".to_string()));
+        assert_eq!(rx.recv().unwrap(), Some(String::new()));
+    }
+
+    #[test]
+    fn closed_tx() {
+        let (mut tx, mut rx) = channel(Bytes).unwrap();
+        tx.send(vec![1, 2, 3]).unwrap();
+        drop(tx);
+        // What was sent before the Sender went away still arrives
+        assert_eq!(rx.recv().unwrap(), Some(vec![1, 2, 3]));
+        assert_eq!(rx.recv().unwrap(), None);
+    }
+
+    #[test]
+    fn closed_rx() {
+        let (mut tx, rx) = channel(Bytes).unwrap();
+        drop(rx);
+        match tx.send(vec![42]) {
+            Err(SendError::Disconnected(v)) => assert_eq!(v, vec![42]),
+            other => panic!("expected Disconnected, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn cut_off_frame() {
+        let (mut tx, rx) = UnixStream::pair().unwrap();
+        let mut rx = Receiver::new(rx, Bytes);
+        tx.write_all(&10u32.to_le_bytes()).unwrap();
+        tx.write_all(b"abc").unwrap();
+        drop(tx);
+        let err = rx.recv().unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+        // Not `Ok(None)`: the Sender did not finish what it was sending
+        assert!(rx.recv().is_err());
+    }
+
+    #[test]
+    fn frame_too_large() {
+        let (mut tx, rx) = UnixStream::pair().unwrap();
+        let mut rx = Receiver::new(rx, Bytes);
+        // Only the length prefix, nobody has to send 4 GiB for the Receiver to allocate it
+        tx.write_all(&u32::MAX.to_le_bytes()).unwrap();
+        let err = rx.recv().unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(rx.frame.capacity() < DEFAULT_MAX_FRAME);
+
+        let (mut tx, mut rx) = channel(Bytes).unwrap();
+        rx.set_max_frame(3);
+        tx.send(vec![1, 2, 3]).unwrap();
+        tx.send(vec![1, 2, 3, 4]).unwrap();
+        assert_eq!(rx.recv().unwrap(), Some(vec![1, 2, 3]));
+        assert_eq!(rx.recv().unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn nothing_is_received_after_a_frame_too_large() {
+        let (mut tx, mut rx) = channel(Bytes).unwrap();
+        rx.set_max_frame(8);
+        tx.send(vec![0; 9]).unwrap();
+        tx.send(vec![7]).unwrap();
+        assert_eq!(rx.recv().unwrap_err().kind(), io::ErrorKind::InvalidData);
+        // The unread payload of the first frame must not be taken for the next length prefix
+        assert_eq!(rx.recv().unwrap_err().kind(), io::ErrorKind::InvalidData);
+        drop(tx);
+        assert!(rx.recv().is_err());
+    }
+
+    #[test]
+    fn connect_and_accept() {
+        let path = std::env::temp_dir().join(format!("channels-ipc-test-{}", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let listener = UnixListener::bind(&path).unwrap();
+        let t = thread::spawn({
+            let path = path.clone();
+            move || {
+                let mut tx = Sender::connect(&path, Bytes).unwrap();
+                // Bigger than the socket buffer, so it takes more than one write
+                for i in 0..4u8 {
+                    tx.send(vec![i; 1 << 20]).unwrap();
+                }
+            }
+        });
+        let mut rx = Receiver::accept(&listener, Bytes).unwrap();
+        for i in 0..4u8 {
+            assert_eq!(rx.recv().unwrap(), Some(vec![i; 1 << 20]));
+        }
+        assert_eq!(rx.recv().unwrap(), None);
+        t.join().unwrap();
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -1,23 +1,123 @@
+pub mod asynchronous;
+pub mod broadcast;
+pub mod codec;
+#[cfg(unix)]
+pub mod ipc;
+pub mod oneshot;
+pub mod priority;
+pub mod select;
+pub mod spill;
+pub mod spsc;
+mod sync;
+pub mod throttle;
+#[cfg(feature = "tracing")]
+pub mod trace;
+
+pub use oneshot::channel as oneshot;
+
 use std::collections::VecDeque;
-use std::sync::{Arc, Condvar, Mutex}; // condvar is a way to announce to another thread that
+use std::fmt;
+use std::sync::PoisonError;
+use std::task::Waker;
+use std::thread;
+use std::time::{Duration, Instant};
+use sync::{Arc, AtomicUsize, Condvar, Mutex, MutexGuard, Ordering}; // condvar is a way to announce to another thread that
 
 pub struct Sender<T> {
     shared: Arc<Shared<T>>,
 }
 
 impl<T> Sender<T> {
-    pub fn send(&mut self, t: T) {
-        let mut inner = self.shared.inner.lock().unwrap(); // Guard or PoisonError (the last thread panicked)
-        inner.queue.push_back(t);
+    /// Fails and hands the value back once the Receiver is gone.
+    pub fn send(&mut self, t: T) -> Result<(), SendError<T>> {
+        let mut inner = self.shared.lock(); // Guard, even if the last thread panicked (see `Shared::lock`)
+        if inner.receivers == 0 {
+            return Err(SendError(t));
+        }
+
+        // A bounded channel makes the Sender wait until the Receiver makes room (backpressure).
+        // A rendezvous channel (capacity 0) still needs one slot to hand the value over.
+        if let Some(capacity) = inner.capacity {
+            while inner.queue.len() >= capacity.max(1) {
+                inner = self.shared.wait(&self.shared.space, inner);
+                if inner.receivers == 0 {
+                    return Err(SendError(t));
+                }
+            }
+        }
+        self.shared.push(&mut inner, t);
+
+        if inner.capacity == Some(0) {
+            // Rendezvous: wait until the Receiver has taken *our* value out of the queue
+            let ticket = inner.taken + inner.queue.len();
+            self.shared.available.notify_one();
+            wake_all(std::mem::take(&mut inner.wakers));
+            while inner.taken < ticket {
+                if inner.receivers == 0 {
+                    // Our value is the only one in the queue, take it back
+                    let t = inner
+                        .queue
+                        .pop_back()
+                        .expect("rendezvous value is still queued");
+                    return Err(SendError(t));
+                }
+                inner = self.shared.wait(&self.shared.space, inner);
+            }
+            return Ok(());
+        }
+        let wakers = std::mem::take(&mut inner.wakers);
         drop(inner); // Drop the lock so the next thread takes the lock
+        wake_all(wakers);
         self.shared.available.notify_one(); // Notify a thread to wake up on that specific Condvar
                                             // Note: This does not notify a specific thread, only a thread that has the specific Condvar
-                                            // In our case we have only one receiver
+                                            // One value is enough for one receiver, so even with many receivers one wakeup will do
+        Ok(())
+    }
+
+    /// Values sent but not received yet: the shared queue plus what the receivers took into their
+    /// own buffers (see `Receiver::recv`) but did not hand out.
+    pub fn len(&self) -> usize {
+        self.shared.len(&self.shared.lock())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// `None` for an unbounded channel.
+    pub fn capacity(&self) -> Option<usize> {
+        self.shared.lock().capacity
+    }
+
+    pub fn sender_count(&self) -> usize {
+        self.shared.lock().senders
+    }
+
+    /// True once every Receiver is gone, `send` will fail from now on.
+    pub fn is_disconnected(&self) -> bool {
+        self.shared.lock().receivers == 0
+    }
+
+    /// The highest `len` since the channel was created (or the last reset).
+    pub fn high_water_mark(&self) -> usize {
+        self.shared.lock().high_water
+    }
+
+    /// Returns the high-water mark and starts tracking again from the current `len`,
+    /// so a metrics exporter can report the peak of every scrape interval.
+    pub fn reset_high_water_mark(&self) -> usize {
+        self.shared.reset_high_water_mark()
+    }
+
+    /// Everything recorded since the last call (see `trace`), oldest first.
+    #[cfg(feature = "tracing")]
+    pub fn take_trace(&self) -> Vec<trace::Event<T>> {
+        self.shared.lock().log.drain()
     }
 }
 impl<T> Clone for Sender<T> {
     fn clone(&self) -> Self {
-        let mut inner = self.shared.inner.lock().unwrap();
+        let mut inner = self.shared.lock();
         inner.senders += 1;
         drop(inner);
         Sender {
@@ -28,53 +128,540 @@ impl<T> Clone for Sender<T> {
 
 impl<T> Drop for Sender<T> {
     fn drop(&mut self) {
-        let mut inner = self.shared.inner.lock().unwrap();
+        let mut inner = self.shared.lock();
         inner.senders -= 1;
+        if thread::panicking() {
+            // Dropped while unwinding: the receivers should know the producer did not just finish
+            inner.panicked = true;
+        }
         let was_last = inner.senders == 0;
-        drop(inner);
         if was_last {
-            self.shared.available.notify_one();
+            let wakers = std::mem::take(&mut inner.wakers);
+            drop(inner);
+            wake_all(wakers);
+            // Every blocked receiver has to find out the channel is disconnected, not just one of them
+            self.shared.available.notify_all();
         }
     }
 }
 pub struct Receiver<T> {
     shared: Arc<Shared<T>>,
+    // Values already taken out of the shared queue. The Receiver swaps the whole queue out in one
+    // lock acquisition and then serves from here without touching the Mutex (see `take_queued`).
+    buffer: VecDeque<T>,
+    #[cfg(feature = "tracing")]
+    buffer_seqs: VecDeque<u64>, // `seq` of each value in `buffer`
 }
 
 impl<T> Receiver<T> {
     pub fn recv(&mut self) -> Option<T> {
-        let mut inner = self.shared.inner.lock().unwrap(); // Guard or PoisonError (the last thread panicked)
+        if let Some(t) = self.pop_buffered() {
+            return Some(t); // No lock needed
+        }
+        let mut inner = self.shared.lock(); // Guard, even if the last thread panicked (see `Shared::lock`)
 
         // Make the Receiver wait for stuff
         loop {
-            match inner.queue.pop_front() {
-                Some(t) => return Some(t), // Release the Mutex
-                None if inner.senders == 0 => return None,
+            if !inner.queue.is_empty() {
+                // Release the Mutex
+                self.shared.take_queued(
+                    inner,
+                    &mut self.buffer,
+                    #[cfg(feature = "tracing")]
+                    &mut self.buffer_seqs,
+                );
+                return self.pop_buffered();
+            }
+            if inner.senders == 0 {
+                return None;
+            }
+            // wait gives the mutex back AND gives up the lock
+            inner = self.shared.wait(&self.shared.available, inner);
+        }
+    }
+
+    /// Never blocks: takes a value only if one is already queued.
+    pub fn try_recv(&mut self) -> Result<T, TryRecvError> {
+        if let Some(t) = self.pop_buffered() {
+            return Ok(t);
+        }
+        let inner = self.shared.lock();
+        if !inner.queue.is_empty() {
+            self.shared.take_queued(
+                inner,
+                &mut self.buffer,
+                #[cfg(feature = "tracing")]
+                &mut self.buffer_seqs,
+            );
+            return Ok(self.pop_buffered().unwrap());
+        }
+        if inner.senders == 0 {
+            Err(inner.disconnected())
+        } else {
+            Err(TryRecvError::Empty)
+        }
+    }
+
+    pub fn recv_timeout(&mut self, timeout: Duration) -> Result<T, RecvTimeoutError> {
+        // A deadline too far away to represent is as good as waiting forever
+        self.recv_until(Instant::now().checked_add(timeout))
+    }
+
+    pub fn recv_deadline(&mut self, deadline: Instant) -> Result<T, RecvTimeoutError> {
+        self.recv_until(Some(deadline))
+    }
+
+    // `recv_deadline` that never times out without a deadline, but still tells a disconnect
+    // apart from a panic (unlike `recv`)
+    fn recv_until(&mut self, deadline: Option<Instant>) -> Result<T, RecvTimeoutError> {
+        if let Some(t) = self.pop_buffered() {
+            return Ok(t);
+        }
+        let mut inner = self.shared.lock();
+        loop {
+            if !inner.queue.is_empty() {
+                self.shared.take_queued(
+                    inner,
+                    &mut self.buffer,
+                    #[cfg(feature = "tracing")]
+                    &mut self.buffer_seqs,
+                );
+                return Ok(self.pop_buffered().unwrap());
+            }
+            if inner.senders == 0 {
+                return Err(match inner.disconnected() {
+                    TryRecvError::Panicked => RecvTimeoutError::Panicked,
+                    _ => RecvTimeoutError::Disconnected,
+                });
+            }
+            let deadline = match deadline {
+                Some(deadline) => deadline,
                 None => {
-                    // wait gives the mutex back AND gives up the lock
-                    inner = self.shared.available.wait(inner).unwrap();
+                    inner = self.shared.wait(&self.shared.available, inner);
+                    continue;
                 }
+            };
+            let now = Instant::now();
+            if now >= deadline {
+                return Err(RecvTimeoutError::Timeout);
+            }
+            // Wakes up on a notification OR when the time runs out, the loop tells them apart
+            inner = self
+                .shared
+                .wait_timeout(&self.shared.available, inner, deadline - now);
+        }
+    }
+
+    /// Moves up to `max` values into `out` with at most one lock acquisition.
+    /// Blocks until at least one value is available and returns how many were moved,
+    /// 0 means every Sender is gone (or `max == 0`).
+    pub fn recv_many(&mut self, out: &mut Vec<T>, max: usize) -> usize {
+        let buffered = self.buffer.len().min(max);
+        out.extend(self.buffer.drain(..buffered));
+        self.shared.buffered.fetch_sub(buffered, Ordering::Relaxed);
+        #[cfg(feature = "tracing")]
+        self.trace_recv(buffered);
+        if buffered == max {
+            return buffered;
+        }
+
+        let mut inner = self.shared.lock();
+        // Only wait if we have nothing to give back yet
+        while buffered == 0 && inner.queue.is_empty() && inner.senders != 0 {
+            inner = self.shared.wait(&self.shared.available, inner);
+        }
+        let n = inner.queue.len().min(max - buffered);
+        out.extend(inner.queue.drain(..n));
+        #[cfg(feature = "tracing")]
+        for _ in 0..n {
+            let seq = inner.seqs.pop_front().unwrap();
+            inner.log.record(trace::Op::Recv, seq, None);
+        }
+        self.shared.took(inner, n);
+        buffered + n
+    }
+
+    /// Yields only what is queued right now, never blocks.
+    pub fn try_iter(&mut self) -> TryIter<'_, T> {
+        TryIter { rx: self }
+    }
+
+    /// See `Sender::len`.
+    pub fn len(&self) -> usize {
+        self.shared.len(&self.shared.lock())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// `None` for an unbounded channel.
+    pub fn capacity(&self) -> Option<usize> {
+        self.shared.lock().capacity
+    }
+
+    pub fn sender_count(&self) -> usize {
+        self.shared.lock().senders
+    }
+
+    /// True once every Sender is gone. There may still be values left to receive.
+    pub fn is_disconnected(&self) -> bool {
+        self.shared.lock().senders == 0
+    }
+
+    /// See `Sender::high_water_mark`.
+    pub fn high_water_mark(&self) -> usize {
+        self.shared.lock().high_water
+    }
+
+    /// See `Sender::reset_high_water_mark`.
+    pub fn reset_high_water_mark(&self) -> usize {
+        self.shared.reset_high_water_mark()
+    }
+
+    /// See `Sender::take_trace`.
+    #[cfg(feature = "tracing")]
+    pub fn take_trace(&self) -> Vec<trace::Event<T>> {
+        self.shared.lock().log.drain()
+    }
+
+    // Hands out the next value this Receiver already took out of the queue
+    fn pop_buffered(&mut self) -> Option<T> {
+        let t = self.buffer.pop_front()?;
+        self.shared.buffered.fetch_sub(1, Ordering::Relaxed);
+        #[cfg(feature = "tracing")]
+        self.trace_recv(1);
+        Some(t)
+    }
+
+    // Records the next `n` values out of `buffer` as received. This is when the caller gets them,
+    // taking them out of the queue (maybe long before) does not count.
+    #[cfg(feature = "tracing")]
+    fn trace_recv(&mut self, n: usize) {
+        let mut inner = self.shared.lock();
+        for seq in self.buffer_seqs.drain(..n) {
+            inner.log.record(trace::Op::Recv, seq, None);
+        }
+    }
+}
+
+// Ends when every Sender is gone and the queue is drained.
+// `IntoIterator` for `Receiver` and `&mut Receiver` comes for free from the blanket impls in std.
+impl<T> Iterator for Receiver<T> {
+    type Item = T;
+    fn next(&mut self) -> Option<Self::Item> {
+        self.recv()
+    }
+}
+
+pub struct TryIter<'a, T> {
+    rx: &'a mut Receiver<T>,
+}
+
+impl<'a, T> Iterator for TryIter<'a, T> {
+    type Item = T;
+    fn next(&mut self) -> Option<Self::Item> {
+        self.rx.try_recv().ok()
+    }
+}
+
+// Receivers can be cloned to share the work: every value still goes to exactly one of them.
+impl<T> Clone for Receiver<T> {
+    fn clone(&self) -> Self {
+        let mut inner = self.shared.lock();
+        inner.receivers += 1;
+        drop(inner);
+        Receiver {
+            shared: Arc::clone(&self.shared),
+            buffer: VecDeque::default(),
+            #[cfg(feature = "tracing")]
+            buffer_seqs: VecDeque::default(),
+        }
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        let mut inner = self.shared.lock();
+        inner.receivers -= 1;
+        if inner.receivers == 0 {
+            // Our buffer goes away with us, it is not waiting to be received any more
+            self.shared
+                .buffered
+                .fetch_sub(self.buffer.len(), Ordering::Relaxed);
+            let wakers = std::mem::take(&mut inner.space_wakers);
+            drop(inner);
+            wake_all(wakers);
+            // Senders blocked on a full (or rendezvous) channel have to find out nobody will make room
+            self.shared.space.notify_all();
+            return;
+        }
+        if !self.buffer.is_empty() {
+            // The other receivers get what we took but did not hand out, in the original order
+            let n = self.buffer.len();
+            while let Some(t) = self.buffer.pop_back() {
+                inner.queue.push_front(t);
+            }
+            // They keep their `seq`: a clone may have taken later values in the meantime
+            #[cfg(feature = "tracing")]
+            while let Some(seq) = self.buffer_seqs.pop_back() {
+                inner.seqs.push_front(seq);
+            }
+            inner.taken -= n;
+            self.shared.buffered.fetch_sub(n, Ordering::Relaxed);
+            let wakers = std::mem::take(&mut inner.wakers);
+            drop(inner);
+            wake_all(wakers);
+            self.shared.available.notify_all();
+        }
+    }
+}
+
+/// Returned by `Sender::send` when the Receiver was dropped. Holds the value that could not be sent.
+#[derive(PartialEq, Eq, Clone, Copy)]
+pub struct SendError<T>(pub T);
+
+impl<T> fmt::Debug for SendError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("SendError { .. }")
+    }
+}
+
+impl<T> fmt::Display for SendError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("sending on a channel with no receiver")
+    }
+}
+
+impl<T> std::error::Error for SendError<T> {}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum TryRecvError {
+    Empty,        // Nothing queued right now, the senders are still around
+    Disconnected, // Nothing queued and every Sender is gone
+    // Like Disconnected, but at least one Sender went away because its thread panicked
+    Panicked,
+}
+
+impl fmt::Display for TryRecvError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TryRecvError::Empty => f.write_str("receiving on an empty channel"),
+            TryRecvError::Disconnected => {
+                f.write_str("receiving on an empty and disconnected channel")
+            }
+            TryRecvError::Panicked => {
+                f.write_str("receiving on an empty channel whose sender panicked")
             }
         }
     }
 }
 
+impl std::error::Error for TryRecvError {}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum RecvTimeoutError {
+    Timeout,      // Nothing arrived before the deadline
+    Disconnected, // Nothing queued and every Sender is gone
+    // Like Disconnected, but at least one Sender went away because its thread panicked
+    Panicked,
+}
+
+impl fmt::Display for RecvTimeoutError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RecvTimeoutError::Timeout => f.write_str("timed out waiting on channel"),
+            RecvTimeoutError::Disconnected => f.write_str("channel is empty and disconnected"),
+            RecvTimeoutError::Panicked => f.write_str("channel is empty and its sender panicked"),
+        }
+    }
+}
+
+impl std::error::Error for RecvTimeoutError {}
+
 struct Inner<T> {
     queue: VecDeque<T>,
     senders: usize,
+    capacity: Option<usize>, // None for an unbounded channel
+    taken: usize,            // How many values the receivers took out of `queue` so far
+    receivers: usize,
+    // Whoever waits for a value without blocking on `available` (`Select`, async receivers) leaves a Waker here.
+    // Senders wake them all (and clear the list) on every send and on disconnect.
+    wakers: Vec<Waker>,
+    // Same for async senders waiting on `space`, woken when values are taken or the last receiver leaves.
+    space_wakers: Vec<Waker>,
+    panicked: bool, // A Sender was dropped while panicking, or someone panicked holding the lock
+    high_water: usize, // Highest `Shared::len` seen, for monitoring
+    #[cfg(feature = "tracing")]
+    log: trace::Log<T>,
+    #[cfg(feature = "tracing")]
+    seqs: VecDeque<u64>, // `seq` of each value in `queue`
+}
+
+impl<T> Inner<T> {
+    fn push(&mut self, t: T) {
+        // The values before this one are either still queued or were taken
+        #[cfg(feature = "tracing")]
+        {
+            let seq = (self.taken + self.queue.len()) as u64;
+            self.log.record(trace::Op::Send, seq, Some(&t));
+            self.seqs.push_back(seq);
+        }
+        self.queue.push_back(t);
+    }
+
+    // Why the receivers will not get anything else, once every Sender is gone
+    fn disconnected(&self) -> TryRecvError {
+        if self.panicked {
+            TryRecvError::Panicked
+        } else {
+            TryRecvError::Disconnected
+        }
+    }
 }
 struct Shared<T> {
     inner: Mutex<Inner<T>>,
-    available: Condvar,
+    available: Condvar, // Receiver waits here for values
+    space: Condvar,     // Senders of a bounded channel wait here for room
+    // Values the receivers took out of `queue` into their buffers and did not hand out yet. Not in
+    // `Inner`: a Receiver hands out of its buffer without taking the lock.
+    buffered: AtomicUsize,
 }
+
+impl<T> Shared<T> {
+    // A thread that panics while it holds the lock poisons the Mutex. Our updates to `Inner` are
+    // small steps that leave it consistent, so rather than panicking in every other thread too
+    // we keep going and remember it happened.
+    fn lock(&self) -> MutexGuard<'_, Inner<T>> {
+        self.inner.lock().unwrap_or_else(recover)
+    }
+
+    fn wait<'a>(
+        &self,
+        cvar: &Condvar,
+        inner: MutexGuard<'a, Inner<T>>,
+    ) -> MutexGuard<'a, Inner<T>> {
+        cvar.wait(inner).unwrap_or_else(recover)
+    }
+
+    fn wait_timeout<'a>(
+        &self,
+        cvar: &Condvar,
+        inner: MutexGuard<'a, Inner<T>>,
+        timeout: Duration,
+    ) -> MutexGuard<'a, Inner<T>> {
+        match cvar.wait_timeout(inner, timeout) {
+            Ok((inner, _)) => inner,
+            Err(poisoned) => recover(PoisonError::new(poisoned.into_inner().0)),
+        }
+    }
+
+    fn push(&self, inner: &mut Inner<T>, t: T) {
+        inner.push(t);
+        inner.high_water = inner.high_water.max(self.len(inner));
+    }
+
+    // Every value sent and not handed out yet, wherever it is
+    fn len(&self, inner: &Inner<T>) -> usize {
+        inner.queue.len() + self.buffered.load(Ordering::Relaxed)
+    }
+
+    fn reset_high_water_mark(&self) -> usize {
+        let mut inner = self.lock();
+        let len = self.len(&inner);
+        std::mem::replace(&mut inner.high_water, len)
+    }
+
+    // Takes every queued value at once when there is a single receiver. `buffer` is empty so the
+    // queue gets the buffer's allocation back. With more receivers we take just one value, otherwise
+    // the first one to wake up would hoard the whole queue while the others sit idle. A bounded
+    // channel also takes just one: emptying the queue would make room for `capacity` more values
+    // while ours are still waiting in the buffer, and the backpressure would be gone.
+    // Under tracing their `seq`s move into `seqs` along with them.
+    fn take_queued(
+        &self,
+        mut inner: MutexGuard<'_, Inner<T>>,
+        buffer: &mut VecDeque<T>,
+        #[cfg(feature = "tracing")] seqs: &mut VecDeque<u64>,
+    ) {
+        if inner.receivers == 1 && inner.capacity.is_none() {
+            std::mem::swap(&mut inner.queue, buffer);
+            #[cfg(feature = "tracing")]
+            std::mem::swap(&mut inner.seqs, seqs);
+        } else {
+            buffer.extend(inner.queue.pop_front());
+            #[cfg(feature = "tracing")]
+            seqs.extend(inner.seqs.pop_front());
+        }
+        let n = buffer.len();
+        self.buffered.fetch_add(n, Ordering::Relaxed);
+        self.took(inner, n);
+    }
+
+    // Bookkeeping after `n` values were taken out of the queue
+    fn took(&self, mut inner: MutexGuard<'_, Inner<T>>, n: usize) {
+        inner.taken += n;
+        let bounded = inner.capacity.is_some();
+        if bounded && n > 0 {
+            let wakers = std::mem::take(&mut inner.space_wakers);
+            drop(inner); // Release the Mutex
+            wake_all(wakers);
+            // Blocked senders wait on `space` either for a free slot or (rendezvous) for their value to be taken
+            self.space.notify_all();
+        }
+    }
+}
+
+fn recover<T>(poisoned: PoisonError<MutexGuard<'_, Inner<T>>>) -> MutexGuard<'_, Inner<T>> {
+    let mut inner = poisoned.into_inner();
+    inner.panicked = true;
+    inner
+}
+
+// Leaves `waker` in `wakers` unless it is already there (a future gets polled many times)
+fn register(wakers: &mut Vec<Waker>, waker: &Waker) {
+    if !wakers.iter().any(|w| w.will_wake(waker)) {
+        wakers.push(waker.clone());
+    }
+}
+
+fn wake_all(wakers: Vec<Waker>) {
+    for waker in wakers {
+        waker.wake();
+    }
+}
+
 pub fn channel<T>() -> (Sender<T>, Receiver<T>) {
+    new_channel(None)
+}
+
+/// A bounded channel: `send` blocks while `capacity` values are queued.
+/// With `capacity == 0` every `send` blocks until the Receiver takes the value (rendezvous).
+pub fn sync_channel<T>(capacity: usize) -> (Sender<T>, Receiver<T>) {
+    new_channel(Some(capacity))
+}
+
+fn new_channel<T>(capacity: Option<usize>) -> (Sender<T>, Receiver<T>) {
     let inner = Inner {
         queue: VecDeque::default(),
         senders: 1,
+        capacity,
+        taken: 0,
+        receivers: 1,
+        wakers: Vec::new(),
+        space_wakers: Vec::new(),
+        panicked: false,
+        high_water: 0,
+        #[cfg(feature = "tracing")]
+        log: trace::Log::new(trace::DEFAULT_LOG_CAPACITY, None),
+        #[cfg(feature = "tracing")]
+        seqs: VecDeque::new(),
     };
     let shared = Shared {
         inner: Mutex::new(inner),
         available: Condvar::new(),
+        space: Condvar::new(),
+        buffered: AtomicUsize::new(0),
     };
     let shared = Arc::new(shared);
     (
@@ -83,16 +670,20 @@ pub fn channel<T>() -> (Sender<T>, Receiver<T>) {
         },
         Receiver {
             shared,
+            buffer: VecDeque::default(),
+            #[cfg(feature = "tracing")]
+            buffer_seqs: VecDeque::default(),
         },
     )
 }
-#[cfg(test)]
+#[cfg(all(test, not(loom)))]
 mod tests {
     use super::*;
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
     #[test]
     fn it_works() {
         let (mut tx, mut rx) = channel();
-        tx.send(42);
+        tx.send(42).unwrap();
         assert_eq!(rx.recv(), Some(42));
     }
 
@@ -106,9 +697,452 @@ mod tests {
 
     #[test]
     fn closed_rx() {
-        // Design decision
+        // Nobody will ever receive the value, so the Sender gets it back
         let (mut tx, rx) = channel();
         drop(rx);
-        tx.send(42);
+        assert_eq!(tx.send(42), Err(SendError(42)));
+    }
+
+    #[test]
+    fn closed_rx_wakes_blocked_sender() {
+        let (mut tx, rx) = sync_channel(1);
+        tx.send(1).unwrap();
+        let t = thread::spawn(move || tx.send(2));
+        thread::sleep(Duration::from_millis(50));
+        drop(rx);
+        assert_eq!(t.join().unwrap(), Err(SendError(2)));
+    }
+
+    #[test]
+    fn closed_rx_rendezvous() {
+        let (mut tx, rx) = sync_channel(0);
+        let t = thread::spawn(move || tx.send(42));
+        thread::sleep(Duration::from_millis(50));
+        drop(rx);
+        assert_eq!(t.join().unwrap(), Err(SendError(42)));
+    }
+
+    #[test]
+    fn bounded_blocks_when_full() {
+        let (mut tx, mut rx) = sync_channel(2);
+        let sent = Arc::new(AtomicUsize::new(0));
+        let counter = sent.clone();
+        let t = thread::spawn(move || {
+            for i in 0..5 {
+                tx.send(i).unwrap();
+                counter.fetch_add(1, Ordering::SeqCst);
+            }
+        });
+        thread::sleep(Duration::from_millis(50));
+        // Only `capacity` values fit before the Sender blocks
+        assert_eq!(sent.load(Ordering::SeqCst), 2);
+        for i in 0..5 {
+            assert_eq!(rx.recv(), Some(i));
+        }
+        t.join().unwrap();
+        assert_eq!(rx.recv(), None);
+    }
+
+    #[test]
+    fn bounded_counts_what_the_receiver_holds() {
+        let (mut tx, mut rx) = sync_channel(4);
+        for i in 0..4 {
+            tx.send(i).unwrap();
+        }
+        assert_eq!(rx.recv(), Some(0));
+        // One value left, so one slot is free, not all four
+        tx.send(4).unwrap();
+        assert_eq!(tx.len(), 4);
+        let t = thread::spawn(move || tx.send(5));
+        thread::sleep(Duration::from_millis(50));
+        assert!(!t.is_finished());
+        assert_eq!(rx.recv(), Some(1));
+        t.join().unwrap().unwrap();
+        assert_eq!(rx.try_iter().collect::<Vec<_>>(), vec![2, 3, 4, 5]);
+    }
+
+    #[test]
+    fn rendezvous() {
+        let (mut tx, mut rx) = sync_channel(0);
+        let done = Arc::new(AtomicBool::new(false));
+        let flag = done.clone();
+        let t = thread::spawn(move || {
+            tx.send(42).unwrap();
+            flag.store(true, Ordering::SeqCst);
+        });
+        thread::sleep(Duration::from_millis(50));
+        // The value sits in the channel but `send` does not return until we take it
+        assert!(!done.load(Ordering::SeqCst));
+        assert_eq!(rx.recv(), Some(42));
+        t.join().unwrap();
+        assert!(done.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn try_recv() {
+        let (mut tx, mut rx) = channel();
+        assert_eq!(rx.try_recv(), Err(TryRecvError::Empty));
+        tx.send(1).unwrap();
+        assert_eq!(rx.try_recv(), Ok(1));
+        drop(tx);
+        assert_eq!(rx.try_recv(), Err(TryRecvError::Disconnected));
+    }
+
+    #[test]
+    fn recv_timeout() {
+        let (mut tx, mut rx) = channel();
+        assert_eq!(
+            rx.recv_timeout(Duration::from_millis(10)),
+            Err(RecvTimeoutError::Timeout)
+        );
+        let t = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(20));
+            tx.send(1).unwrap();
+        });
+        assert_eq!(rx.recv_timeout(Duration::from_secs(5)), Ok(1));
+        t.join().unwrap();
+        assert_eq!(
+            rx.recv_timeout(Duration::from_secs(5)),
+            Err(RecvTimeoutError::Disconnected)
+        );
+    }
+
+    #[test]
+    fn recv_deadline_in_the_past() {
+        let (mut tx, mut rx) = channel();
+        let deadline = Instant::now();
+        assert_eq!(rx.recv_deadline(deadline), Err(RecvTimeoutError::Timeout));
+        // A value that is already there is returned even if the deadline passed
+        tx.send(1).unwrap();
+        assert_eq!(rx.recv_deadline(deadline), Ok(1));
+    }
+
+    #[test]
+    fn recv_drains_in_order() {
+        let (mut tx, mut rx) = channel();
+        for i in 0..3 {
+            tx.send(i).unwrap();
+        }
+        assert_eq!(rx.recv(), Some(0)); // Takes all three out of the shared queue
+        tx.send(3).unwrap();
+        assert_eq!(rx.try_recv(), Ok(1));
+        assert_eq!(rx.recv(), Some(2));
+        assert_eq!(rx.recv(), Some(3));
+    }
+
+    #[test]
+    fn recv_many() {
+        let (mut tx, mut rx) = channel();
+        for i in 0..5 {
+            tx.send(i).unwrap();
+        }
+        let mut out = Vec::new();
+        assert_eq!(rx.recv_many(&mut out, 2), 2);
+        assert_eq!(rx.recv(), Some(2));
+        assert_eq!(rx.recv_many(&mut out, 10), 2);
+        assert_eq!(out, vec![0, 1, 3, 4]);
+        drop(tx);
+        assert_eq!(rx.recv_many(&mut out, 10), 0);
+    }
+
+    #[test]
+    fn recv_many_blocks_until_something_arrives() {
+        let (mut tx, mut rx) = sync_channel(1);
+        let t = thread::spawn(move || {
+            for i in 0..100 {
+                tx.send(i).unwrap();
+            }
+        });
+        let mut out = Vec::new();
+        while rx.recv_many(&mut out, 16) != 0 {}
+        t.join().unwrap();
+        assert_eq!(out, (0..100).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn iter() {
+        let (mut tx, rx) = channel();
+        let t = thread::spawn(move || {
+            for i in 0..3 {
+                tx.send(i).unwrap();
+            }
+        });
+        // Ends once the Sender is dropped at the end of the thread
+        assert_eq!(rx.collect::<Vec<_>>(), vec![0, 1, 2]);
+        t.join().unwrap();
+    }
+
+    #[test]
+    fn into_iter_by_ref() {
+        let (mut tx, mut rx) = channel();
+        tx.send(1).unwrap();
+        tx.send(2).unwrap();
+        drop(tx);
+        let mut sum = 0;
+        for i in &mut rx {
+            sum += i;
+        }
+        assert_eq!(sum, 3);
+        assert_eq!(rx.recv(), None);
+    }
+
+    #[test]
+    fn try_iter() {
+        let (mut tx, mut rx) = channel();
+        assert_eq!(rx.try_iter().next(), None);
+        tx.send(1).unwrap();
+        tx.send(2).unwrap();
+        // The Sender is still alive, but `try_iter` stops at the end of what is queued
+        assert_eq!(rx.try_iter().collect::<Vec<_>>(), vec![1, 2]);
+        tx.send(3).unwrap();
+        assert_eq!(rx.try_iter().collect::<Vec<_>>(), vec![3]);
+    }
+
+    #[test]
+    fn mpmc_exactly_once() {
+        let (tx, rx) = sync_channel(16);
+        let producers: Vec<_> = (0..4)
+            .map(|p| {
+                let mut tx = tx.clone();
+                thread::spawn(move || {
+                    for i in 0..1000 {
+                        tx.send(p * 1000 + i).unwrap();
+                    }
+                })
+            })
+            .collect();
+        drop(tx);
+        let consumers: Vec<_> = (0..8)
+            .map(|_| {
+                let rx = rx.clone();
+                thread::spawn(move || rx.collect::<Vec<_>>())
+            })
+            .collect();
+        drop(rx);
+        for p in producers {
+            p.join().unwrap();
+        }
+        let mut all: Vec<_> = consumers
+            .into_iter()
+            .flat_map(|c| c.join().unwrap())
+            .collect();
+        all.sort_unstable();
+        assert_eq!(all, (0..4000).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn disconnect_wakes_every_receiver() {
+        let (tx, rx) = channel::<()>();
+        let receivers: Vec<_> = (0..4)
+            .map(|_| {
+                let mut rx = rx.clone();
+                thread::spawn(move || rx.recv())
+            })
+            .collect();
+        thread::sleep(Duration::from_millis(50));
+        drop(tx);
+        for r in receivers {
+            assert_eq!(r.join().unwrap(), None);
+        }
+    }
+
+    #[test]
+    fn dropped_receiver_hands_back_its_buffer() {
+        let (mut tx, mut rx) = channel();
+        for i in 0..3 {
+            tx.send(i).unwrap();
+        }
+        assert_eq!(rx.recv(), Some(0)); // Sole receiver, 1 and 2 are now in its buffer
+        let mut other = rx.clone();
+        drop(rx);
+        assert_eq!(other.try_iter().collect::<Vec<_>>(), vec![1, 2]);
+    }
+
+    #[test]
+    fn closed_rx_only_after_last_receiver() {
+        let (mut tx, rx) = channel();
+        let rx2 = rx.clone();
+        drop(rx);
+        assert_eq!(tx.send(1), Ok(()));
+        drop(rx2);
+        assert_eq!(tx.send(2), Err(SendError(2)));
+    }
+
+    #[test]
+    fn sender_panics() {
+        let (mut tx, mut rx) = channel();
+        let t = thread::spawn(move || {
+            tx.send(1).unwrap();
+            panic!("producer failed");
+        });
+        assert!(t.join().is_err());
+        // What was sent before the panic still arrives
+        assert_eq!(rx.try_recv(), Ok(1));
+        assert_eq!(rx.try_recv(), Err(TryRecvError::Panicked));
+        assert_eq!(
+            rx.recv_timeout(Duration::from_millis(10)),
+            Err(RecvTimeoutError::Panicked)
+        );
+        // Same without a deadline that can be represented
+        assert_eq!(
+            rx.recv_timeout(Duration::MAX),
+            Err(RecvTimeoutError::Panicked)
+        );
+        assert_eq!(rx.recv(), None);
+    }
+
+    #[test]
+    fn sender_panics_mid_send() {
+        let (tx, mut rx) = channel();
+        let mut other = tx.clone();
+        let t = thread::spawn(move || {
+            // Panic while holding the lock, halfway through a send
+            let mut inner = tx.shared.inner.lock().unwrap();
+            inner.push(1);
+            panic!("producer failed mid-send");
+        });
+        assert!(t.join().is_err());
+        assert!(rx.shared.inner.is_poisoned());
+        // Neither the receiver nor the other senders panic because of it
+        other.send(2).unwrap();
+        assert_eq!(rx.recv(), Some(1));
+        assert_eq!(rx.recv(), Some(2));
+        drop(other);
+        assert_eq!(rx.try_recv(), Err(TryRecvError::Panicked));
+    }
+
+    #[test]
+    fn clean_disconnect_is_not_a_panic() {
+        let (tx, mut rx) = channel::<()>();
+        let t = thread::spawn(move || drop(tx));
+        t.join().unwrap();
+        assert_eq!(rx.try_recv(), Err(TryRecvError::Disconnected));
+    }
+
+    #[test]
+    fn introspection() {
+        let (mut tx, mut rx) = sync_channel(4);
+        assert_eq!(tx.capacity(), Some(4));
+        assert!(tx.is_empty() && rx.is_empty());
+        for i in 0..3 {
+            tx.send(i).unwrap();
+        }
+        assert_eq!(tx.len(), 3);
+        assert_eq!(rx.len(), 3);
+        assert_eq!(rx.recv(), Some(0));
+        assert_eq!(tx.len(), 2);
+        assert_eq!(rx.len(), 2);
+
+        let tx2 = tx.clone();
+        assert_eq!(rx.sender_count(), 2);
+        drop(tx);
+        drop(tx2);
+        assert!(rx.is_disconnected());
+        assert_eq!(rx.sender_count(), 0);
+
+        let (tx, rx) = channel::<()>();
+        assert_eq!(tx.capacity(), None);
+        assert!(!tx.is_disconnected());
+        drop(rx);
+        assert!(tx.is_disconnected());
+    }
+
+    #[test]
+    fn high_water_mark() {
+        let (mut tx, mut rx) = channel();
+        for i in 0..5 {
+            tx.send(i).unwrap();
+        }
+        assert_eq!(rx.recv_many(&mut Vec::new(), 3), 3);
+        tx.send(5).unwrap();
+        assert_eq!(tx.high_water_mark(), 5);
+        assert_eq!(rx.reset_high_water_mark(), 5);
+        // Starts again from what is queued right now
+        assert_eq!(tx.high_water_mark(), 3);
+    }
+
+    #[test]
+    fn len_counts_the_receivers_buffer() {
+        let (mut tx, mut rx) = channel();
+        for i in 0..1000 {
+            tx.send(i).unwrap();
+        }
+        assert_eq!(rx.recv(), Some(0)); // The other 999 are in the Receiver's buffer now
+        for i in 1000..1500 {
+            tx.send(i).unwrap();
+        }
+        assert_eq!(tx.len(), 1499);
+        assert_eq!(rx.len(), 1499);
+        assert_eq!(tx.high_water_mark(), 1499);
+        assert_eq!(rx.recv_many(&mut Vec::new(), 10), 10);
+        assert_eq!(tx.len(), 1489);
+        // A dropped Receiver hands its buffer back, nothing is counted twice
+        let other = rx.clone();
+        drop(rx);
+        assert_eq!(other.len(), 1489);
+        assert_eq!(other.reset_high_water_mark(), 1499);
+        assert_eq!(other.high_water_mark(), 1489);
+
+        // The last Receiver drops its buffer along with it
+        let (mut tx, mut rx) = channel();
+        for i in 0..3 {
+            tx.send(i).unwrap();
+        }
+        assert_eq!(rx.recv(), Some(0));
+        drop(rx);
+        assert_eq!(tx.len(), 0);
+        assert!(tx.is_empty());
+    }
+}
+
+// Exhaustive versions of the wakeup tests above: loom runs the closure once for every possible
+// interleaving of the threads, and fails if one of them ends with a thread that is never woken up.
+// RUSTFLAGS="--cfg loom" cargo test -p channels --release --lib loom
+#[cfg(all(test, loom))]
+mod loom_tests {
+    use super::*;
+    use loom::thread;
+
+    #[test]
+    fn loom_last_sender_drop_wakes_receiver() {
+        loom::model(|| {
+            let (tx, mut rx) = channel::<i32>();
+            let tx2 = tx.clone();
+            let t1 = thread::spawn(move || drop(tx));
+            let t2 = thread::spawn(move || drop(tx2));
+            // Blocks until whichever Sender goes last notices and wakes us
+            assert_eq!(rx.recv(), None);
+            t1.join().unwrap();
+            t2.join().unwrap();
+        });
+    }
+
+    #[test]
+    fn loom_recv_sees_values_before_disconnect() {
+        loom::model(|| {
+            let (mut tx, mut rx) = channel();
+            let t = thread::spawn(move || {
+                tx.send(1).unwrap();
+                tx.send(2).unwrap();
+            });
+            // No interleaving may report the disconnect before both values came out
+            assert_eq!(rx.recv(), Some(1));
+            assert_eq!(rx.recv(), Some(2));
+            assert_eq!(rx.recv(), None);
+            t.join().unwrap();
+        });
+    }
+
+    #[test]
+    fn loom_rendezvous_sender_drop() {
+        loom::model(|| {
+            let (mut tx, mut rx) = sync_channel(0);
+            let t = thread::spawn(move || {
+                tx.send(1).unwrap();
+            });
+            assert_eq!(rx.recv(), Some(1));
+            assert_eq!(rx.recv(), None);
+            t.join().unwrap();
+        });
     }
 }
@@ -0,0 +1,302 @@
+// A channel for exactly one value (request/response).
+// No queue and no sender counting: a single slot plus an atomic state that says what is in it.
+// Whoever changes the state away from EMPTY wakes the Receiver's Waker, which is either a task
+// (the Receiver is a Future) or a blocked thread (a `Signal`, same as `Select` uses).
+use crate::select::Signal;
+use crate::sync::{self, Arc, AtomicU8, Mutex, Ordering};
+use crate::{RecvTimeoutError, SendError, TryRecvError};
+use std::cell::UnsafeCell;
+use std::fmt;
+use std::future::Future;
+use std::mem::MaybeUninit;
+use std::pin::Pin;
+use std::task::{Context, Poll, Waker};
+use std::time::{Duration, Instant};
+
+const EMPTY: u8 = 0; // Nothing sent yet and both halves are alive
+const SENT: u8 = 1; // The value is in the slot
+const TAKEN: u8 = 2; // The Receiver took the value
+const CLOSED: u8 = 3; // One of the halves went away
+
+pub struct Sender<T> {
+    inner: Arc<Inner<T>>,
+}
+
+impl<T> Sender<T> {
+    /// Consumes the Sender, there is only ever one value. Hands it back if the Receiver is gone.
+    pub fn send(self, t: T) -> Result<(), SendError<T>> {
+        let inner = &*self.inner;
+        // Safety: only the Sender writes the slot, and the Receiver does not read it before the state is SENT
+        unsafe { (*inner.value.get()).write(t) };
+        // Release: the write above is visible to the Receiver once it sees SENT
+        match inner
+            .state
+            .compare_exchange(EMPTY, SENT, Ordering::AcqRel, Ordering::Acquire)
+        {
+            Ok(_) => {
+                inner.wake();
+                Ok(())
+            }
+            // The Receiver is gone (CLOSED), take the value back out
+            // Safety: we just wrote it and nobody else will read it
+            Err(_) => Err(SendError(unsafe {
+                (*inner.value.get()).assume_init_read()
+            })),
+        }
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        // Only matters if we never sent: then the Receiver has to find out nothing is coming
+        if self
+            .inner
+            .state
+            .compare_exchange(EMPTY, CLOSED, Ordering::AcqRel, Ordering::Acquire)
+            .is_ok()
+        {
+            self.inner.wake();
+        }
+    }
+}
+
+pub struct Receiver<T> {
+    inner: Arc<Inner<T>>,
+}
+
+impl<T> Receiver<T> {
+    pub fn recv(mut self) -> Result<T, RecvError> {
+        match self.wait(None) {
+            Ok(t) => Ok(t),
+            Err(_) => Err(RecvError),
+        }
+    }
+
+    pub fn recv_timeout(&mut self, timeout: Duration) -> Result<T, RecvTimeoutError> {
+        match Instant::now().checked_add(timeout) {
+            Some(deadline) => self.wait(Some(deadline)),
+            None => self.wait(None),
+        }
+    }
+
+    pub fn try_recv(&mut self) -> Result<T, TryRecvError> {
+        let inner = &*self.inner;
+        match inner.state.load(Ordering::Acquire) {
+            SENT => {
+                // Only the Receiver moves the state away from SENT
+                inner.state.store(TAKEN, Ordering::Relaxed);
+                // Safety: SENT means the Sender wrote the slot and is done with it
+                Ok(unsafe { (*inner.value.get()).assume_init_read() })
+            }
+            EMPTY => Err(TryRecvError::Empty),
+            _ => Err(TryRecvError::Disconnected), // Sender dropped, or we took the value already
+        }
+    }
+
+    // Returns Ready if there is no point in waiting, otherwise leaves `waker` for the Sender
+    fn poll_recv(&mut self, waker: &Waker) -> Poll<Result<T, TryRecvError>> {
+        match self.try_recv() {
+            Err(TryRecvError::Empty) => {}
+            ready => return Poll::Ready(ready),
+        }
+        *sync::lock(&self.inner.waker) = Some(waker.clone());
+        // The Sender may have come by between `try_recv` and storing the Waker
+        match self.try_recv() {
+            Err(TryRecvError::Empty) => Poll::Pending,
+            ready => Poll::Ready(ready),
+        }
+    }
+
+    fn wait(&mut self, deadline: Option<Instant>) -> Result<T, RecvTimeoutError> {
+        // A Waker is built from std's Arc, even under loom
+        let signal = std::sync::Arc::new(Signal::default());
+        let waker = Waker::from(std::sync::Arc::clone(&signal));
+        loop {
+            match self.poll_recv(&waker) {
+                Poll::Ready(Ok(t)) => return Ok(t),
+                Poll::Ready(Err(_)) => return Err(RecvTimeoutError::Disconnected),
+                Poll::Pending => {}
+            }
+            if !signal.wait(deadline) {
+                return Err(RecvTimeoutError::Timeout);
+            }
+        }
+    }
+}
+
+impl<T> Future for Receiver<T> {
+    type Output = Result<T, RecvError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        match self.get_mut().poll_recv(cx.waker()) {
+            Poll::Ready(Ok(t)) => Poll::Ready(Ok(t)),
+            Poll::Ready(Err(_)) => Poll::Ready(Err(RecvError)),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        if self.inner.state.swap(CLOSED, Ordering::AcqRel) == SENT {
+            // Sent but never received
+            // Safety: SENT means the slot holds a value and the Sender is done with it
+            unsafe { (*self.inner.value.get()).assume_init_drop() };
+        }
+    }
+}
+
+/// The Sender was dropped without sending anything.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct RecvError;
+
+impl fmt::Display for RecvError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("oneshot sender dropped without sending")
+    }
+}
+
+impl std::error::Error for RecvError {}
+
+struct Inner<T> {
+    state: AtomicU8,
+    value: UnsafeCell<MaybeUninit<T>>,
+    waker: Mutex<Option<Waker>>, // The Receiver's, for the Sender to wake
+}
+
+// Safety: the slot is handed from the Sender to the Receiver through `state`
+unsafe impl<T> Sync for Inner<T> where T: Send {}
+
+impl<T> Inner<T> {
+    fn wake(&self) {
+        let waker = sync::lock(&self.waker).take();
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+}
+
+pub fn channel<T>() -> (Sender<T>, Receiver<T>) {
+    let inner = Arc::new(Inner {
+        state: AtomicU8::new(EMPTY),
+        value: UnsafeCell::new(MaybeUninit::uninit()),
+        waker: Mutex::new(None),
+    });
+    (
+        Sender {
+            inner: Arc::clone(&inner),
+        },
+        Receiver { inner },
+    )
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use super::*;
+    use futures::executor::block_on;
+    use std::thread;
+
+    #[test]
+    fn it_works() {
+        let (tx, rx) = channel();
+        tx.send(42).unwrap();
+        assert_eq!(rx.recv(), Ok(42));
+    }
+
+    #[test]
+    fn closed() {
+        let (tx, rx) = channel::<()>();
+        drop(tx);
+        assert_eq!(rx.recv(), Err(RecvError));
+    }
+
+    #[test]
+    fn closed_rx() {
+        let (tx, rx) = channel();
+        drop(rx);
+        assert_eq!(tx.send(42), Err(SendError(42)));
+    }
+
+    #[test]
+    fn recv_blocks_until_send() {
+        let (tx, rx) = channel();
+        let t = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            tx.send(42).unwrap();
+        });
+        assert_eq!(rx.recv(), Ok(42));
+        t.join().unwrap();
+    }
+
+    #[test]
+    fn recv_wakes_up_when_sender_drops() {
+        let (tx, rx) = channel::<()>();
+        let t = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            drop(tx);
+        });
+        assert_eq!(rx.recv(), Err(RecvError));
+        t.join().unwrap();
+    }
+
+    #[test]
+    fn try_and_timeout() {
+        let (tx, mut rx) = channel();
+        assert_eq!(rx.try_recv(), Err(TryRecvError::Empty));
+        assert_eq!(
+            rx.recv_timeout(Duration::from_millis(10)),
+            Err(RecvTimeoutError::Timeout)
+        );
+        tx.send(1).unwrap();
+        assert_eq!(rx.recv_timeout(Duration::from_millis(10)), Ok(1));
+        // There is only one value
+        assert_eq!(rx.try_recv(), Err(TryRecvError::Disconnected));
+    }
+
+    #[test]
+    fn future() {
+        let (tx, rx) = channel();
+        let t = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            tx.send("reply").unwrap();
+        });
+        assert_eq!(block_on(rx), Ok("reply"));
+        t.join().unwrap();
+    }
+
+    #[test]
+    fn drops_unreceived_value() {
+        let value = Arc::new(());
+        let (tx, rx) = channel();
+        tx.send(Arc::clone(&value)).unwrap();
+        drop(rx);
+        assert_eq!(Arc::strong_count(&value), 1);
+    }
+}
+
+// RUSTFLAGS="--cfg loom" cargo test -p channels --release --lib loom
+#[cfg(all(test, loom))]
+mod loom_tests {
+    use super::*;
+    use loom::thread;
+
+    #[test]
+    fn loom_send_wakes_receiver() {
+        loom::model(|| {
+            let (tx, rx) = channel();
+            let t = thread::spawn(move || tx.send(1).unwrap());
+            assert_eq!(rx.recv(), Ok(1));
+            t.join().unwrap();
+        });
+    }
+
+    #[test]
+    fn loom_sender_drop_wakes_receiver() {
+        loom::model(|| {
+            let (tx, rx) = channel::<i32>();
+            let t = thread::spawn(move || drop(tx));
+            assert_eq!(rx.recv(), Err(RecvError));
+            t.join().unwrap();
+        });
+    }
+}
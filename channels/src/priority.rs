@@ -0,0 +1,275 @@
+// A channel that hands out the highest priority value first instead of the oldest.
+// Same Mutex + Condvar design as `crate::channel`, but the queue is a BinaryHeap.
+// Every value also gets a sequence number so values of equal priority still come out in FIFO order.
+use crate::sync::{self, Arc, Condvar, Mutex};
+use crate::{RecvTimeoutError, SendError, TryRecvError};
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::thread;
+use std::time::{Duration, Instant};
+
+pub struct Sender<P, T> {
+    shared: Arc<Shared<P, T>>,
+}
+
+impl<P: Ord, T> Sender<P, T> {
+    /// Fails and hands the value back once the Receiver is gone.
+    pub fn send_with_priority(&mut self, priority: P, t: T) -> Result<(), SendError<T>> {
+        let mut inner = sync::lock(&self.shared.inner);
+        if !inner.receiver_alive {
+            return Err(SendError(t));
+        }
+        let seq = inner.next_seq;
+        inner.next_seq += 1;
+        inner.heap.push(Entry {
+            priority,
+            seq,
+            value: t,
+        });
+        drop(inner);
+        self.shared.available.notify_one();
+        Ok(())
+    }
+}
+
+impl<P, T> Clone for Sender<P, T> {
+    fn clone(&self) -> Self {
+        let mut inner = sync::lock(&self.shared.inner);
+        inner.senders += 1;
+        drop(inner);
+        Sender {
+            shared: Arc::clone(&self.shared),
+        }
+    }
+}
+
+impl<P, T> Drop for Sender<P, T> {
+    fn drop(&mut self) {
+        let mut inner = sync::lock(&self.shared.inner);
+        inner.senders -= 1;
+        if thread::panicking() {
+            inner.panicked = true; // See `crate::Sender`'s Drop
+        }
+        let was_last = inner.senders == 0;
+        drop(inner);
+        if was_last {
+            self.shared.available.notify_one();
+        }
+    }
+}
+
+pub struct Receiver<P, T> {
+    shared: Arc<Shared<P, T>>,
+}
+
+impl<P: Ord, T> Receiver<P, T> {
+    /// The highest priority value queued, waiting for one if there is none.
+    pub fn recv(&mut self) -> Option<T> {
+        let mut inner = sync::lock(&self.shared.inner);
+        loop {
+            match inner.heap.pop() {
+                Some(entry) => return Some(entry.value),
+                None if inner.senders == 0 => return None,
+                None => inner = sync::wait(&self.shared.available, inner),
+            }
+        }
+    }
+
+    pub fn try_recv(&mut self) -> Result<T, TryRecvError> {
+        let mut inner = sync::lock(&self.shared.inner);
+        match inner.heap.pop() {
+            Some(entry) => Ok(entry.value),
+            None if inner.senders == 0 => Err(inner.disconnected()),
+            None => Err(TryRecvError::Empty),
+        }
+    }
+
+    pub fn recv_timeout(&mut self, timeout: Duration) -> Result<T, RecvTimeoutError> {
+        // A deadline too far away to represent is as good as waiting forever
+        let deadline = Instant::now().checked_add(timeout);
+        let mut inner = sync::lock(&self.shared.inner);
+        loop {
+            match inner.heap.pop() {
+                Some(entry) => return Ok(entry.value),
+                None if inner.senders == 0 => {
+                    return Err(match inner.disconnected() {
+                        TryRecvError::Panicked => RecvTimeoutError::Panicked,
+                        _ => RecvTimeoutError::Disconnected,
+                    })
+                }
+                None => {
+                    let deadline = match deadline {
+                        Some(deadline) => deadline,
+                        None => {
+                            inner = sync::wait(&self.shared.available, inner);
+                            continue;
+                        }
+                    };
+                    let now = Instant::now();
+                    if now >= deadline {
+                        return Err(RecvTimeoutError::Timeout);
+                    }
+                    inner = sync::wait_timeout(&self.shared.available, inner, deadline - now);
+                }
+            }
+        }
+    }
+}
+
+impl<P, T> Drop for Receiver<P, T> {
+    fn drop(&mut self) {
+        sync::lock(&self.shared.inner).receiver_alive = false;
+    }
+}
+
+// What goes into the heap. Only `priority` and `seq` take part in the ordering.
+struct Entry<P, T> {
+    priority: P,
+    seq: u64,
+    value: T,
+}
+
+impl<P: Ord, T> Ord for Entry<P, T> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // BinaryHeap is a max-heap: higher priority first, then the *lower* sequence number (older) first
+        self.priority
+            .cmp(&other.priority)
+            .then_with(|| other.seq.cmp(&self.seq))
+    }
+}
+
+impl<P: Ord, T> PartialOrd for Entry<P, T> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<P: Ord, T> PartialEq for Entry<P, T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl<P: Ord, T> Eq for Entry<P, T> {}
+
+struct Inner<P, T> {
+    heap: BinaryHeap<Entry<P, T>>,
+    next_seq: u64,
+    senders: usize,
+    receiver_alive: bool,
+    panicked: bool, // A Sender was dropped while panicking
+}
+
+impl<P, T> Inner<P, T> {
+    fn disconnected(&self) -> TryRecvError {
+        if self.panicked {
+            TryRecvError::Panicked
+        } else {
+            TryRecvError::Disconnected
+        }
+    }
+}
+
+struct Shared<P, T> {
+    inner: Mutex<Inner<P, T>>,
+    available: Condvar,
+}
+
+/// `P` is the priority, larger values are received first.
+pub fn channel<P: Ord, T>() -> (Sender<P, T>, Receiver<P, T>) {
+    let inner = Inner {
+        heap: BinaryHeap::new(),
+        next_seq: 0,
+        senders: 1,
+        receiver_alive: true,
+        panicked: false,
+    };
+    let shared = Arc::new(Shared {
+        inner: Mutex::new(inner),
+        available: Condvar::new(),
+    });
+    (
+        Sender {
+            shared: Arc::clone(&shared),
+        },
+        Receiver { shared },
+    )
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn highest_priority_first() {
+        let (mut tx, mut rx) = channel();
+        tx.send_with_priority(1, "data").unwrap();
+        tx.send_with_priority(10, "control").unwrap();
+        tx.send_with_priority(5, "other").unwrap();
+        assert_eq!(rx.recv(), Some("control"));
+        assert_eq!(rx.recv(), Some("other"));
+        assert_eq!(rx.recv(), Some("data"));
+    }
+
+    #[test]
+    fn fifo_among_equals() {
+        let (mut tx, mut rx) = channel();
+        for i in 0..5 {
+            tx.send_with_priority(0, i).unwrap();
+        }
+        tx.send_with_priority(1, 100).unwrap();
+        drop(tx);
+        assert_eq!(rx.recv(), Some(100));
+        assert_eq!(
+            std::iter::from_fn(|| rx.recv()).collect::<Vec<_>>(),
+            vec![0, 1, 2, 3, 4]
+        );
+    }
+
+    #[test]
+    fn closed() {
+        let (tx, mut rx) = channel::<u8, ()>();
+        drop(tx);
+        assert_eq!(rx.recv(), None);
+        assert_eq!(rx.try_recv(), Err(TryRecvError::Disconnected));
+    }
+
+    #[test]
+    fn sender_panics() {
+        let (mut tx, mut rx) = channel();
+        let t = thread::spawn(move || {
+            tx.send_with_priority(0, 1).unwrap();
+            panic!("producer failed");
+        });
+        assert!(t.join().is_err());
+        assert_eq!(rx.try_recv(), Ok(1));
+        assert_eq!(rx.try_recv(), Err(TryRecvError::Panicked));
+        assert_eq!(
+            rx.recv_timeout(Duration::MAX),
+            Err(RecvTimeoutError::Panicked)
+        );
+    }
+
+    #[test]
+    fn closed_rx() {
+        let (mut tx, rx) = channel();
+        drop(rx);
+        assert_eq!(tx.send_with_priority(0, 42), Err(SendError(42)));
+    }
+
+    #[test]
+    fn recv_blocks_until_send() {
+        let (mut tx, mut rx) = channel();
+        assert_eq!(
+            rx.recv_timeout(Duration::from_millis(10)),
+            Err(RecvTimeoutError::Timeout)
+        );
+        let t = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(20));
+            tx.send_with_priority(0, 1).unwrap();
+        });
+        assert_eq!(rx.recv(), Some(1));
+        t.join().unwrap();
+        assert_eq!(rx.recv(), None);
+    }
+}
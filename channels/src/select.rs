@@ -0,0 +1,263 @@
+// Waiting on several Receivers at once.
+// A Receiver can only block on its own `available` Condvar, so instead `Select` hands every
+// channel the same Waker (see `Inner::wakers`) and sleeps on a Condvar of its own.
+// Whichever channel gets a value (or disconnects) first wakes it up.
+use crate::sync::{self, Condvar, Mutex};
+use crate::Receiver;
+use std::sync::Arc; // std's even under loom: `Wake` is only implemented for it
+use std::task::{Wake, Waker};
+use std::time::{Duration, Instant};
+
+pub struct Select<'a> {
+    handles: Vec<&'a dyn Selectable>,
+}
+
+impl<'a> Select<'a> {
+    pub fn new() -> Self {
+        Select {
+            handles: Vec::new(),
+        }
+    }
+
+    /// Registers `rx` and returns its index, which is what `select` returns once `rx` is ready.
+    pub fn recv<T>(&mut self, rx: &'a Receiver<T>) -> usize {
+        self.handles.push(rx);
+        self.handles.len() - 1
+    }
+
+    /// Blocks until one of the receivers has a value or is disconnected, and returns its index.
+    /// It does not take the value, follow up with `try_recv` on that receiver.
+    pub fn select(&mut self) -> usize {
+        self.wait(None).expect("no deadline, so no timeout")
+    }
+
+    pub fn select_timeout(&mut self, timeout: Duration) -> Option<usize> {
+        match Instant::now().checked_add(timeout) {
+            Some(deadline) => self.wait(Some(deadline)),
+            None => self.wait(None),
+        }
+    }
+
+    /// Never blocks: the index of a receiver that is ready right now, if any.
+    pub fn try_select(&mut self) -> Option<usize> {
+        self.handles.iter().position(|h| h.is_ready())
+    }
+
+    fn wait(&mut self, deadline: Option<Instant>) -> Option<usize> {
+        assert!(
+            !self.handles.is_empty(),
+            "select with no receivers would block forever"
+        );
+        let signal = Arc::new(Signal::default());
+        let waker = Waker::from(Arc::clone(&signal));
+        loop {
+            // Checking and registering happen under the channel's lock, so a send that happens
+            // right after the check finds our Waker and we do not miss it.
+            if let Some(index) = self.handles.iter().position(|h| h.register(&waker)) {
+                self.unregister(&waker);
+                return Some(index);
+            }
+            if !signal.wait(deadline) {
+                self.unregister(&waker);
+                return None;
+            }
+        }
+    }
+
+    fn unregister(&self, waker: &Waker) {
+        for handle in &self.handles {
+            handle.unregister(waker);
+        }
+    }
+}
+
+impl Default for Select<'_> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// What `Select` needs from a receiver, independent of its `T`.
+pub(crate) trait Selectable {
+    fn is_ready(&self) -> bool;
+    // Returns true if ready, otherwise leaves `waker` to be woken on the next send or disconnect
+    fn register(&self, waker: &Waker) -> bool;
+    fn unregister(&self, waker: &Waker);
+}
+
+impl<T> Selectable for Receiver<T> {
+    fn is_ready(&self) -> bool {
+        if !self.buffer.is_empty() {
+            return true;
+        }
+        let inner = self.shared.lock();
+        !inner.queue.is_empty() || inner.senders == 0
+    }
+
+    fn register(&self, waker: &Waker) -> bool {
+        if !self.buffer.is_empty() {
+            return true;
+        }
+        let mut inner = self.shared.lock();
+        if !inner.queue.is_empty() || inner.senders == 0 {
+            return true;
+        }
+        crate::register(&mut inner.wakers, waker);
+        false
+    }
+
+    fn unregister(&self, waker: &Waker) {
+        let mut inner = self.shared.lock();
+        inner.wakers.retain(|w| !w.will_wake(waker));
+    }
+}
+
+// A Waker that a blocking thread can sleep on.
+#[derive(Default)]
+pub(crate) struct Signal {
+    woken: Mutex<bool>,
+    cvar: Condvar,
+}
+
+impl Signal {
+    // Sleeps until woken (and resets), false if the deadline passed first
+    pub(crate) fn wait(&self, deadline: Option<Instant>) -> bool {
+        let mut woken = sync::lock(&self.woken);
+        while !*woken {
+            match deadline {
+                None => woken = sync::wait(&self.cvar, woken),
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        return false;
+                    }
+                    woken = sync::wait_timeout(&self.cvar, woken, deadline - now);
+                }
+            }
+        }
+        *woken = false;
+        true
+    }
+}
+
+impl Wake for Signal {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        *sync::lock(&self.woken) = true;
+        self.cvar.notify_one();
+    }
+}
+
+/// Blocks until one of the receivers is ready and runs the matching arm.
+/// `msg` is bound to the receiver's `try_recv()`: `Err(Disconnected)` if every Sender is gone,
+/// `Err(Empty)` only if a clone of that receiver took the value first.
+///
+// Under loom the channels' Mutex and Condvar only work inside `loom::model`, so the example can't run there
+#[cfg_attr(not(loom), doc = "```")]
+#[cfg_attr(loom, doc = "```ignore")]
+/// # let (mut tx, mut a) = channels::channel::<i32>();
+/// # let (_tx, mut b) = channels::channel::<&str>();
+/// # tx.send(1).unwrap();
+/// channels::select! {
+///     recv(a) -> msg => assert_eq!(msg, Ok(1)),
+///     recv(b) -> _msg => unreachable!(),
+/// }
+/// ```
+#[macro_export]
+macro_rules! select {
+    ($(recv($rx:expr) -> $msg:pat => $body:expr),+ $(,)?) => {{
+        let index = {
+            let mut select = $crate::select::Select::new();
+            $( select.recv(&$rx); )+
+            select.select()
+        };
+        let mut _arm = 0usize;
+        $(
+            if index == { _arm += 1; _arm - 1 } {
+                let $msg = $rx.try_recv();
+                $body
+            } else
+        )+
+        {
+            unreachable!()
+        }
+    }};
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use super::*;
+    use crate::{channel, TryRecvError};
+    use std::thread;
+
+    #[test]
+    fn picks_the_ready_one() {
+        let (_tx1, rx1) = channel::<i32>();
+        let (mut tx2, mut rx2) = channel();
+        tx2.send(2).unwrap();
+        let mut select = Select::new();
+        assert_eq!(select.recv(&rx1), 0);
+        assert_eq!(select.recv(&rx2), 1);
+        assert_eq!(select.select(), 1);
+        drop(select);
+        assert_eq!(rx2.try_recv(), Ok(2));
+    }
+
+    #[test]
+    fn wakes_up_on_send() {
+        let (_tx1, rx1) = channel::<i32>();
+        let (mut tx2, rx2) = channel();
+        let t = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            tx2.send(2).unwrap();
+            tx2
+        });
+        let mut select = Select::new();
+        select.recv(&rx1);
+        select.recv(&rx2);
+        assert_eq!(select.select(), 1);
+        drop(t.join().unwrap());
+    }
+
+    #[test]
+    fn wakes_up_on_disconnect() {
+        let (_tx1, rx1) = channel::<i32>();
+        let (tx2, mut rx2) = channel::<i32>();
+        let t = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            drop(tx2);
+        });
+        let mut select = Select::new();
+        select.recv(&rx1);
+        select.recv(&rx2);
+        assert_eq!(select.select(), 1);
+        drop(select);
+        assert_eq!(rx2.try_recv(), Err(TryRecvError::Disconnected));
+        t.join().unwrap();
+    }
+
+    #[test]
+    fn timeout_cleans_up_wakers() {
+        let (_tx, rx) = channel::<i32>();
+        let mut select = Select::new();
+        select.recv(&rx);
+        assert_eq!(select.try_select(), None);
+        assert_eq!(select.select_timeout(Duration::from_millis(10)), None);
+        assert!(rx.shared.inner.lock().unwrap().wakers.is_empty());
+    }
+
+    #[test]
+    fn macro_runs_the_matching_arm() {
+        let (_tx1, mut rx1) = channel::<i32>();
+        let (mut tx2, mut rx2) = channel();
+        tx2.send("two").unwrap();
+        let got = crate::select! {
+            recv(rx1) -> _msg => unreachable!(),
+            recv(rx2) -> msg => msg,
+        };
+        assert_eq!(got, Ok("two"));
+    }
+}
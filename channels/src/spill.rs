@@ -0,0 +1,545 @@
+// A channel that keeps up to `limit` values in memory and spills the rest to a file.
+// Once something is spilled, every newer value goes to the file too (otherwise it would overtake
+// the spilled ones), until the Receiver has read the file back. The file is append-only with
+// length-prefixed frames, it is truncated whenever it has been fully read and removed on drop.
+// A write that fails partway leaves half a frame behind, and every frame appended after it would be
+// read from the wrong offset. So after a failed write nothing more is spilled (the sends fail)
+// until the Receiver has read back the frames before it and the file starts over.
+use crate::codec::Codec;
+use crate::sync::{self, Arc, Condvar, Mutex};
+use std::collections::VecDeque;
+use std::convert::TryFrom;
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+pub struct Sender<T, C> {
+    shared: Arc<Shared<T, C>>,
+}
+
+impl<T, C: Codec<T>> Sender<T, C> {
+    /// Never blocks: past the memory limit the value goes to disk instead.
+    pub fn send(&mut self, t: T) -> Result<(), SendError<T>> {
+        let mut inner = sync::lock(&self.shared.inner);
+        if !inner.receiver_alive {
+            return Err(SendError::Disconnected(t));
+        }
+        if inner.spilled == 0 && inner.memory.len() < self.shared.limit {
+            inner.memory.push_back(t);
+        } else if let Err(e) = inner.spill(&self.shared, &t) {
+            return Err(SendError::Io(t, e));
+        }
+        drop(inner);
+        self.shared.available.notify_one();
+        Ok(())
+    }
+}
+
+impl<T, C> Clone for Sender<T, C> {
+    fn clone(&self) -> Self {
+        let mut inner = sync::lock(&self.shared.inner);
+        inner.senders += 1;
+        drop(inner);
+        Sender {
+            shared: Arc::clone(&self.shared),
+        }
+    }
+}
+
+impl<T, C> Drop for Sender<T, C> {
+    fn drop(&mut self) {
+        let mut inner = sync::lock(&self.shared.inner);
+        inner.senders -= 1;
+        let was_last = inner.senders == 0;
+        drop(inner);
+        if was_last {
+            self.shared.available.notify_one();
+        }
+    }
+}
+
+pub struct Receiver<T, C> {
+    shared: Arc<Shared<T, C>>,
+}
+
+impl<T, C: Codec<T>> Receiver<T, C> {
+    /// `Ok(None)` once every Sender is gone and nothing is left in memory or on disk.
+    pub fn recv(&mut self) -> io::Result<Option<T>> {
+        let mut inner = sync::lock(&self.shared.inner);
+        loop {
+            if let Some((0, _)) = inner.read_error {
+                return Err(inner.read_error.take().unwrap().1);
+            }
+            if let Some(t) = inner.memory.pop_front() {
+                if let Some((ahead, _)) = &mut inner.read_error {
+                    *ahead -= 1;
+                }
+                return Ok(Some(t));
+            }
+            if inner.spilled > 0 {
+                inner.refill(&self.shared)?;
+                continue;
+            }
+            if inner.senders == 0 {
+                return Ok(None);
+            }
+            inner = sync::wait(&self.shared.available, inner);
+        }
+    }
+
+    /// Values waiting, in memory and on disk.
+    pub fn len(&self) -> usize {
+        let inner = sync::lock(&self.shared.inner);
+        inner.memory.len() + inner.spilled
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<T, C> Drop for Receiver<T, C> {
+    fn drop(&mut self) {
+        sync::lock(&self.shared.inner).receiver_alive = false;
+    }
+}
+
+pub enum SendError<T> {
+    Disconnected(T),  // The Receiver is gone
+    Io(T, io::Error), // Writing the value to the spill file failed
+}
+
+impl<T> SendError<T> {
+    pub fn into_inner(self) -> T {
+        match self {
+            SendError::Disconnected(t) | SendError::Io(t, _) => t,
+        }
+    }
+}
+
+impl<T> fmt::Debug for SendError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SendError::Disconnected(_) => f.write_str("Disconnected(..)"),
+            SendError::Io(_, e) => f.debug_tuple("Io").field(&"..").field(e).finish(),
+        }
+    }
+}
+
+impl<T> fmt::Display for SendError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SendError::Disconnected(_) => f.write_str("sending on a channel with no receiver"),
+            SendError::Io(_, e) => write!(f, "spilling to disk failed: {}", e),
+        }
+    }
+}
+
+impl<T> std::error::Error for SendError<T> {}
+
+struct Inner<T> {
+    memory: VecDeque<T>,
+    spill: Option<SpillFile>, // Created the first time we run out of memory
+    spilled: usize,           // Values in the file that were not read back yet
+    // A frame that could not be read back, and how many values in `memory` were sent before it.
+    // `recv` hands those out first, so the error comes in the place of the lost value.
+    read_error: Option<(usize, io::Error)>,
+    senders: usize,
+    receiver_alive: bool,
+}
+
+impl<T> Inner<T> {
+    fn spill<C: Codec<T>>(&mut self, shared: &Shared<T, C>, t: &T) -> io::Result<()> {
+        let mut frame = vec![0; 4]; // Room for the length prefix
+        shared.codec.encode(t, &mut frame)?;
+        let len = u32::try_from(frame.len() - 4)
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "value too large to spill"))?;
+        frame[..4].copy_from_slice(&len.to_le_bytes());
+        if self.spill.is_none() {
+            self.spill = Some(SpillFile::create(&shared.dir)?);
+        }
+        let file = self.spill.as_mut().unwrap();
+        if file.failed {
+            if self.spilled > 0 {
+                return Err(io::Error::other(
+                    "spill file is unusable after a failed write until it is read back",
+                ));
+            }
+            file.reset()?; // Nothing in it worth keeping
+        }
+        if let Err(e) = file.writer.write_all(&frame) {
+            file.failed = true;
+            return Err(e);
+        }
+        self.spilled += 1;
+        Ok(())
+    }
+
+    // Reads spilled values back into memory, up to the limit so we do not read the whole file at once
+    fn refill<C: Codec<T>>(&mut self, shared: &Shared<T, C>) -> io::Result<()> {
+        let file = self
+            .spill
+            .as_mut()
+            .expect("spilled values without a spill file");
+        file.writer.flush()?;
+        let mut frame = Vec::new();
+        while self.spilled > 0 && self.memory.len() < shared.limit.max(1) {
+            if let Err(e) = read_frame(&mut file.reader, &mut frame) {
+                // We stopped somewhere inside a frame, every frame after it would be read from the
+                // wrong offset: the rest of the file is lost
+                self.spilled = 0;
+                self.read_error = Some((self.memory.len(), e));
+                break;
+            }
+            // The frame is out of the file whether or not it decodes, a bad one is lost
+            self.spilled -= 1;
+            match shared.codec.decode(&frame) {
+                Ok(t) => self.memory.push_back(t),
+                Err(e) => {
+                    self.read_error = Some((self.memory.len(), e));
+                    break;
+                }
+            }
+        }
+        if self.spilled == 0 {
+            // Everything was read back: start over so the file does not grow forever
+            file.reset()?;
+        }
+        Ok(())
+    }
+}
+
+fn read_frame(reader: &mut impl Read, frame: &mut Vec<u8>) -> io::Result<()> {
+    let mut len = [0; 4];
+    reader.read_exact(&mut len)?;
+    frame.resize(u32::from_le_bytes(len) as usize, 0);
+    reader.read_exact(frame)
+}
+
+struct SpillFile {
+    path: PathBuf,
+    writer: BufWriter<File>,
+    reader: BufReader<File>,
+    failed: bool, // A write failed partway, see the top of this file
+}
+
+impl SpillFile {
+    fn create(dir: &Path) -> io::Result<Self> {
+        // Unique per process and per channel
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+        let name = format!(
+            "channels-spill-{}-{}.bin",
+            process::id(),
+            NEXT.fetch_add(1, Ordering::Relaxed)
+        );
+        let path = dir.join(name);
+        let writer = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&path)?;
+        let reader = File::open(&path)?;
+        Ok(SpillFile {
+            path,
+            writer: BufWriter::new(writer),
+            reader: BufReader::new(reader),
+            failed: false,
+        })
+    }
+
+    fn reset(&mut self) -> io::Result<()> {
+        self.writer.flush()?;
+        self.writer.get_mut().set_len(0)?;
+        self.writer.seek(SeekFrom::Start(0))?;
+        self.reader.seek(SeekFrom::Start(0))?;
+        self.failed = false;
+        Ok(())
+    }
+}
+
+impl Drop for SpillFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+struct Shared<T, C> {
+    inner: Mutex<Inner<T>>,
+    available: Condvar,
+    codec: C,
+    limit: usize,
+    dir: PathBuf,
+}
+
+/// Keeps up to `limit` values in memory, the rest go to a file in `dir` encoded with `codec`.
+pub fn channel<T, C: Codec<T>>(
+    limit: usize,
+    dir: impl Into<PathBuf>,
+    codec: C,
+) -> (Sender<T, C>, Receiver<T, C>) {
+    let inner = Inner {
+        memory: VecDeque::new(),
+        spill: None,
+        spilled: 0,
+        read_error: None,
+        senders: 1,
+        receiver_alive: true,
+    };
+    let shared = Arc::new(Shared {
+        inner: Mutex::new(inner),
+        available: Condvar::new(),
+        codec,
+        limit,
+        dir: dir.into(),
+    });
+    (
+        Sender {
+            shared: Arc::clone(&shared),
+        },
+        Receiver { shared },
+    )
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use super::*;
+    use crate::codec::Utf8;
+    use std::convert::TryInto;
+    use std::thread;
+
+    // Each test gets its own directory so the file checks do not see each other, removed when
+    // the test is done
+    struct TestDir(PathBuf);
+
+    impl Drop for TestDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    impl std::ops::Deref for TestDir {
+        type Target = Path;
+        fn deref(&self) -> &Path {
+            &self.0
+        }
+    }
+
+    fn dir(name: &str) -> TestDir {
+        let dir =
+            std::env::temp_dir().join(format!("channels-spill-test-{}-{}", name, process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        TestDir(dir)
+    }
+
+    fn files(dir: &Path) -> usize {
+        fs::read_dir(dir).unwrap().count()
+    }
+
+    struct U32;
+
+    impl Codec<u32> for U32 {
+        fn encode(&self, value: &u32, buf: &mut Vec<u8>) -> io::Result<()> {
+            buf.extend_from_slice(&value.to_le_bytes());
+            Ok(())
+        }
+
+        fn decode(&self, buf: &[u8]) -> io::Result<u32> {
+            let bytes = buf
+                .try_into()
+                .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "expected 4 bytes"))?;
+            Ok(u32::from_le_bytes(bytes))
+        }
+    }
+
+    // Like `U32`, but 3 does not decode
+    struct No3;
+
+    impl Codec<u32> for No3 {
+        fn encode(&self, value: &u32, buf: &mut Vec<u8>) -> io::Result<()> {
+            U32.encode(value, buf)
+        }
+
+        fn decode(&self, buf: &[u8]) -> io::Result<u32> {
+            match U32.decode(buf)? {
+                3 => Err(io::Error::new(io::ErrorKind::InvalidData, "no 3")),
+                value => Ok(value),
+            }
+        }
+    }
+
+    #[test]
+    fn stays_in_memory_under_the_limit() {
+        let dir = dir("memory");
+        let (mut tx, mut rx) = channel(4, &*dir, U32);
+        for i in 0..4 {
+            tx.send(i).unwrap();
+        }
+        assert_eq!(files(&dir), 0);
+        for i in 0..4 {
+            assert_eq!(rx.recv().unwrap(), Some(i));
+        }
+    }
+
+    #[test]
+    fn spills_in_fifo_order_and_cleans_up() {
+        let dir = dir("fifo");
+        let (mut tx, mut rx) = channel(2, &*dir, Utf8);
+        for i in 0..10 {
+            tx.send(i.to_string()).unwrap();
+        }
+        assert_eq!(files(&dir), 1);
+        assert_eq!(rx.len(), 10);
+        // Reading some back frees memory, but newer values must still queue up behind the spilled ones
+        assert_eq!(rx.recv().unwrap(), Some("0".to_string()));
+        assert_eq!(rx.recv().unwrap(), Some("1".to_string()));
+        assert_eq!(rx.recv().unwrap(), Some("2".to_string()));
+        tx.send("10".to_string()).unwrap();
+        drop(tx);
+        let rest: Vec<_> = std::iter::from_fn(|| rx.recv().unwrap()).collect();
+        assert_eq!(rest, (3..=10).map(|i| i.to_string()).collect::<Vec<_>>());
+        drop(rx);
+        assert_eq!(files(&dir), 0);
+    }
+
+    #[test]
+    fn file_is_reused_after_draining() {
+        let dir = dir("reuse");
+        let (mut tx, mut rx) = channel(1, &*dir, U32);
+        for round in 0..3 {
+            for i in 0..5 {
+                tx.send(round * 10 + i).unwrap();
+            }
+            for i in 0..5 {
+                assert_eq!(rx.recv().unwrap(), Some(round * 10 + i));
+            }
+            let inner = sync::lock(&rx.shared.inner);
+            let file = inner.spill.as_ref().unwrap();
+            assert_eq!(file.writer.get_ref().metadata().unwrap().len(), 0);
+        }
+    }
+
+    #[test]
+    fn decode_error_loses_only_that_value() {
+        let dir = dir("decode");
+        let (mut tx, mut rx) = channel(1, &*dir, No3);
+        for i in 0..6 {
+            tx.send(i).unwrap();
+        }
+        for i in 0..3 {
+            assert_eq!(rx.recv().unwrap(), Some(i));
+        }
+        assert_eq!(rx.recv().unwrap_err().kind(), io::ErrorKind::InvalidData);
+        assert_eq!(rx.recv().unwrap(), Some(4));
+        assert_eq!(rx.recv().unwrap(), Some(5));
+        assert!(rx.is_empty());
+        // Still in step with the file after it started over
+        tx.send(6).unwrap();
+        tx.send(7).unwrap();
+        assert_eq!(rx.recv().unwrap(), Some(6));
+        assert_eq!(rx.recv().unwrap(), Some(7));
+        drop(tx);
+        assert_eq!(rx.recv().unwrap(), None);
+    }
+
+    #[test]
+    fn decode_error_comes_in_order() {
+        let dir = dir("decode-order");
+        let (mut tx, mut rx) = channel(2, &*dir, No3);
+        for i in 0..4 {
+            tx.send(i).unwrap();
+        }
+        assert_eq!(rx.recv().unwrap(), Some(0));
+        assert_eq!(rx.recv().unwrap(), Some(1));
+        // 2 and 3 are read back together, 2 was sent before the bad one
+        assert_eq!(rx.recv().unwrap(), Some(2));
+        // Sent after it, and in memory since the file is drained
+        tx.send(4).unwrap();
+        assert_eq!(rx.recv().unwrap_err().kind(), io::ErrorKind::InvalidData);
+        assert_eq!(rx.recv().unwrap(), Some(4));
+
+        let (mut tx, mut rx) = channel(2, &*dir, No3);
+        for i in 0..10 {
+            tx.send(i).unwrap();
+        }
+        drop(tx);
+        let got: Vec<_> = std::iter::from_fn(|| rx.recv().transpose())
+            .map(|r| r.ok())
+            .collect();
+        let mut expected: Vec<_> = (0..10).map(Some).collect();
+        expected[3] = None;
+        assert_eq!(got, expected);
+    }
+
+    #[test]
+    fn cut_off_frame_drops_the_rest_of_the_file() {
+        let dir = dir("cut-off");
+        let (mut tx, mut rx) = channel(1, &*dir, U32);
+        for i in 0..4 {
+            tx.send(i).unwrap();
+        }
+        {
+            // 1 is whole, 2 is cut off halfway through its value, 3 is gone
+            let mut inner = sync::lock(&tx.shared.inner);
+            let file = inner.spill.as_mut().unwrap();
+            file.writer.flush().unwrap();
+            file.writer.get_ref().set_len(8 + 6).unwrap();
+        }
+        assert_eq!(rx.recv().unwrap(), Some(0));
+        assert_eq!(rx.recv().unwrap(), Some(1));
+        assert_eq!(rx.recv().unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+        assert!(rx.is_empty());
+        // The file started over
+        for i in 10..13 {
+            tx.send(i).unwrap();
+        }
+        for i in 10..13 {
+            assert_eq!(rx.recv().unwrap(), Some(i));
+        }
+    }
+
+    #[test]
+    fn failed_write_stops_spilling_until_drained() {
+        let dir = dir("failed");
+        let (mut tx, mut rx) = channel(1, &*dir, U32);
+        for i in 0..3 {
+            tx.send(i).unwrap();
+        }
+        // As if writing the next frame had failed partway
+        sync::lock(&tx.shared.inner).spill.as_mut().unwrap().failed = true;
+        assert!(matches!(tx.send(3), Err(SendError::Io(3, _))));
+        // The frames before it are fine, and once they are read back the file is usable again
+        for i in 0..3 {
+            assert_eq!(rx.recv().unwrap(), Some(i));
+        }
+        for i in 4..7 {
+            tx.send(i).unwrap();
+        }
+        for i in 4..7 {
+            assert_eq!(rx.recv().unwrap(), Some(i));
+        }
+    }
+
+    #[test]
+    fn closed_rx() {
+        let dir = dir("closed");
+        let (mut tx, rx) = channel(1, &*dir, U32);
+        drop(rx);
+        assert!(matches!(tx.send(42), Err(SendError::Disconnected(42))));
+    }
+
+    #[test]
+    fn across_threads() {
+        let dir = dir("threads");
+        let (mut tx, mut rx) = channel(8, &*dir, U32);
+        let t = thread::spawn(move || {
+            for i in 0..1000 {
+                tx.send(i).unwrap();
+            }
+        });
+        let got: Vec<_> = std::iter::from_fn(|| rx.recv().unwrap()).collect();
+        t.join().unwrap();
+        assert_eq!(got, (0..1000).collect::<Vec<_>>());
+    }
+}
@@ -0,0 +1,320 @@
+// Single-producer single-consumer bounded channel on a fixed ring buffer.
+// No Mutex, no Condvar: the Sender only moves `tail`, the Receiver only moves `head`,
+// and each side only reads the other one's index to know if there is something to do.
+// Neither half is Clone, so the type system guarantees there is exactly one of each.
+use crate::sync::{self, Arc, AtomicBool, AtomicUsize, Ordering};
+use crate::{SendError, TryRecvError};
+use std::cell::UnsafeCell;
+use std::mem::MaybeUninit;
+
+pub struct Sender<T> {
+    ring: Arc<Ring<T>>,
+}
+
+impl<T> Sender<T> {
+    /// Spins (and then yields) while the ring is full.
+    pub fn send(&mut self, t: T) -> Result<(), SendError<T>> {
+        let ring = &*self.ring;
+        let tail = ring.tail.load(Ordering::Relaxed); // Only we write `tail`
+        let mut backoff = Backoff::default();
+        loop {
+            if !ring.receiver_alive.load(Ordering::Acquire) {
+                return Err(SendError(t));
+            }
+            // Acquire: the Receiver is done reading the slot before it moves `head` past it
+            let head = ring.head.load(Ordering::Acquire);
+            if ring.len(head, tail) < ring.slots.len() {
+                break;
+            }
+            backoff.snooze();
+        }
+        // Safety: the slot is between `head` and `head + capacity` so the Receiver will not touch it
+        // until we publish it by moving `tail`.
+        unsafe { (*ring.slot(tail)).write(t) };
+        // Release: the write above is visible to whoever sees the new `tail`
+        ring.tail.store(ring.next(tail), Ordering::Release);
+        Ok(())
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        // Release: the last `tail` store happens before the Receiver sees we are gone
+        self.ring.sender_alive.store(false, Ordering::Release);
+    }
+}
+
+pub struct Receiver<T> {
+    ring: Arc<Ring<T>>,
+}
+
+impl<T> Receiver<T> {
+    /// Spins (and then yields) while the ring is empty. `None` once the Sender is gone and the ring is drained.
+    pub fn recv(&mut self) -> Option<T> {
+        let mut backoff = Backoff::default();
+        loop {
+            match self.try_recv() {
+                Ok(t) => return Some(t),
+                Err(TryRecvError::Disconnected) | Err(TryRecvError::Panicked) => return None,
+                Err(TryRecvError::Empty) => backoff.snooze(),
+            }
+        }
+    }
+
+    pub fn try_recv(&mut self) -> Result<T, TryRecvError> {
+        let ring = &*self.ring;
+        let head = ring.head.load(Ordering::Relaxed); // Only we write `head`
+        if ring.tail.load(Ordering::Acquire) == head {
+            if ring.sender_alive.load(Ordering::Acquire) {
+                return Err(TryRecvError::Empty);
+            }
+            // The Sender may have pushed one last value right before it dropped, look again
+            if ring.tail.load(Ordering::Acquire) == head {
+                return Err(TryRecvError::Disconnected);
+            }
+        }
+        // Safety: `head != tail` so the Sender has written this slot and will not touch it
+        // until we move `head` past it.
+        let t = unsafe { (*ring.slot(head)).assume_init_read() };
+        // Release: we are done with the slot before the Sender can reuse it
+        ring.head.store(ring.next(head), Ordering::Release);
+        Ok(t)
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        self.ring.receiver_alive.store(false, Ordering::Release);
+    }
+}
+
+struct Ring<T> {
+    slots: Box<[UnsafeCell<MaybeUninit<T>>]>,
+    // Indices count up to `2 * capacity` and then start again at 0. Counting to twice the capacity
+    // tells a full ring (`tail` one lap ahead) apart from an empty one (`head == tail`), and unlike
+    // letting them wrap at `usize::MAX` it works for any capacity: `usize::MAX + 1` is not a multiple
+    // of 3, so there index `usize::MAX` and the index after it would both be slot 0.
+    head: AtomicUsize, // Next slot to read
+    tail: AtomicUsize, // Next slot to write
+    sender_alive: AtomicBool,
+    receiver_alive: AtomicBool,
+}
+
+// Safety: a slot is only ever accessed by one side at a time, handed over through `head`/`tail`
+unsafe impl<T> Sync for Ring<T> where T: Send {}
+
+impl<T> Ring<T> {
+    fn slot(&self, index: usize) -> *mut MaybeUninit<T> {
+        self.slots[index % self.slots.len()].get()
+    }
+
+    fn next(&self, index: usize) -> usize {
+        if index + 1 == 2 * self.slots.len() {
+            0
+        } else {
+            index + 1
+        }
+    }
+
+    // How many values are between `head` and `tail`
+    fn len(&self, head: usize, tail: usize) -> usize {
+        if tail >= head {
+            tail - head
+        } else {
+            tail + 2 * self.slots.len() - head
+        }
+    }
+}
+
+impl<T> Drop for Ring<T> {
+    fn drop(&mut self) {
+        // Both halves are gone (we own the last Arc), drop whatever was sent but never received
+        let tail = self.tail.load(Ordering::Relaxed);
+        let mut head = self.head.load(Ordering::Relaxed);
+        while head != tail {
+            // Safety: every slot between `head` and `tail` holds an initialized value
+            unsafe { (*self.slot(head)).assume_init_drop() };
+            head = self.next(head);
+        }
+    }
+}
+
+// Spin a little first (the other side is usually just about to make progress), then give up the CPU.
+#[derive(Default)]
+struct Backoff {
+    step: u32,
+}
+
+impl Backoff {
+    fn snooze(&mut self) {
+        if self.step < 6 {
+            for _ in 0..1 << self.step {
+                sync::spin_loop();
+            }
+            self.step += 1;
+        } else {
+            sync::yield_now();
+        }
+    }
+}
+
+/// A bounded channel holding at most `capacity` values.
+pub fn channel<T>(capacity: usize) -> (Sender<T>, Receiver<T>) {
+    assert!(
+        capacity > 0,
+        "spsc channel needs room for at least one value"
+    );
+    assert!(
+        capacity <= usize::MAX / 2,
+        "spsc channel capacity too large"
+    );
+    let ring = Ring {
+        slots: (0..capacity)
+            .map(|_| UnsafeCell::new(MaybeUninit::uninit()))
+            .collect(),
+        head: AtomicUsize::new(0),
+        tail: AtomicUsize::new(0),
+        sender_alive: AtomicBool::new(true),
+        receiver_alive: AtomicBool::new(true),
+    };
+    let ring = Arc::new(ring);
+    (
+        Sender {
+            ring: Arc::clone(&ring),
+        },
+        Receiver { ring },
+    )
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use super::*;
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn it_works() {
+        let (mut tx, mut rx) = channel(4);
+        tx.send(42).unwrap();
+        assert_eq!(rx.recv(), Some(42));
+    }
+
+    #[test]
+    fn closed() {
+        let (tx, mut rx) = channel::<()>(1);
+        drop(tx);
+        assert_eq!(rx.recv(), None);
+    }
+
+    #[test]
+    fn closed_rx() {
+        let (mut tx, rx) = channel(1);
+        drop(rx);
+        assert_eq!(tx.send(42), Err(SendError(42)));
+    }
+
+    #[test]
+    fn value_sent_before_drop_is_received() {
+        let (mut tx, mut rx) = channel(2);
+        tx.send(1).unwrap();
+        drop(tx);
+        assert_eq!(rx.try_recv(), Ok(1));
+        assert_eq!(rx.try_recv(), Err(TryRecvError::Disconnected));
+    }
+
+    #[test]
+    fn blocks_when_full() {
+        let (mut tx, mut rx) = channel(2);
+        let t = thread::spawn(move || {
+            for i in 0..3 {
+                tx.send(i).unwrap();
+            }
+        });
+        thread::sleep(Duration::from_millis(50));
+        assert!(!t.is_finished()); // The third value does not fit
+        assert_eq!(rx.recv(), Some(0));
+        t.join().unwrap();
+        assert_eq!(rx.recv(), Some(1));
+        assert_eq!(rx.recv(), Some(2));
+        assert_eq!(rx.recv(), None);
+    }
+
+    #[test]
+    fn wraps_around() {
+        let (mut tx, mut rx) = channel(3);
+        let t = thread::spawn(move || {
+            for i in 0..10_000 {
+                tx.send(i).unwrap();
+            }
+        });
+        assert!((0..10_000).eq(std::iter::from_fn(|| rx.recv())));
+        t.join().unwrap();
+    }
+
+    #[test]
+    fn indices_wrap_at_twice_the_capacity() {
+        let (tx, _rx) = channel::<u8>(3);
+        let ring = &tx.ring;
+        let mut index = 0;
+        let mut seen = Vec::new();
+        for _ in 0..12 {
+            seen.push(index);
+            index = ring.next(index);
+        }
+        assert_eq!(seen, vec![0, 1, 2, 3, 4, 5, 0, 1, 2, 3, 4, 5]);
+        // Full and empty stay apart across the wrap
+        assert_eq!(ring.len(4, 1), 3);
+        assert_eq!(ring.len(5, 5), 0);
+        assert_eq!(ring.len(5, 0), 1);
+        // Every slot shows up once per lap
+        let slots: Vec<_> = (0..6).map(|i| ring.slot(i)).collect();
+        assert_eq!(slots[..3], slots[3..]);
+        assert!(slots[0] != slots[1] && slots[1] != slots[2] && slots[0] != slots[2]);
+    }
+
+    #[test]
+    fn drops_unreceived_values() {
+        let value = Arc::new(());
+        let (mut tx, rx) = channel(4);
+        tx.send(Arc::clone(&value)).unwrap();
+        tx.send(Arc::clone(&value)).unwrap();
+        assert_eq!(Arc::strong_count(&value), 3);
+        drop(tx);
+        drop(rx);
+        assert_eq!(Arc::strong_count(&value), 1);
+    }
+}
+
+// RUSTFLAGS="--cfg loom" cargo test -p channels --release --lib loom
+#[cfg(all(test, loom))]
+mod loom_tests {
+    use super::*;
+    use loom::thread;
+
+    #[test]
+    fn loom_values_arrive_in_order_through_a_full_ring() {
+        loom::model(|| {
+            let (mut tx, mut rx) = channel(1);
+            let t = thread::spawn(move || {
+                tx.send(1).unwrap();
+                tx.send(2).unwrap();
+            });
+            assert_eq!(rx.recv(), Some(1));
+            assert_eq!(rx.recv(), Some(2));
+            assert_eq!(rx.recv(), None);
+            t.join().unwrap();
+        });
+    }
+
+    #[test]
+    fn loom_receiver_drop_stops_sender() {
+        loom::model(|| {
+            let (mut tx, rx) = channel(1);
+            let t = thread::spawn(move || drop(rx));
+            // Either the value fits before the Receiver goes away or it comes back, never a hang
+            let _ = tx.send(1);
+            let _ = tx.send(2);
+            t.join().unwrap();
+        });
+    }
+}
@@ -0,0 +1,303 @@
+// Controlling how fast values go through a channel.
+// `Throttled` limits a Sender with a token bucket: the bucket holds up to `burst` tokens, refills at
+// `rate` tokens per second, and every send takes one. `Coalesce` works on the receiving end: it
+// collects a burst of values and only hands out the latest one for every key.
+use crate::{Receiver, SendError, Sender};
+use std::collections::hash_map::{Entry, HashMap};
+use std::collections::VecDeque;
+use std::fmt;
+use std::hash::Hash;
+use std::thread;
+use std::time::{Duration, Instant};
+
+pub struct Throttled<S> {
+    inner: S,
+    bucket: TokenBucket,
+}
+
+impl<T> Throttled<Sender<T>> {
+    /// Starts with a full bucket, so the first `burst` values go through right away.
+    pub fn new(tx: Sender<T>, rate: f64, burst: u32) -> Self {
+        assert!(rate > 0.0, "throttle rate must be positive");
+        assert!(burst > 0, "throttle burst must allow at least one value");
+        Throttled {
+            inner: tx,
+            bucket: TokenBucket {
+                tokens: f64::from(burst),
+                burst: f64::from(burst),
+                rate,
+                last: Instant::now(),
+            },
+        }
+    }
+
+    /// Sleeps until there is a token, then sends like `Sender::send`.
+    pub fn send(&mut self, t: T) -> Result<(), SendError<T>> {
+        while let Err(wait) = self.bucket.take(Instant::now()) {
+            thread::sleep(wait);
+        }
+        // Nothing went through if it fails, the token was not used
+        self.inner.send(t).inspect_err(|_| self.bucket.give_back())
+    }
+
+    /// Fails with `WouldBlock` instead of waiting for a token.
+    pub fn try_send(&mut self, t: T) -> Result<(), TrySendError<T>> {
+        if self.bucket.take(Instant::now()).is_err() {
+            return Err(TrySendError::WouldBlock(t));
+        }
+        self.inner.send(t).map_err(|SendError(t)| {
+            self.bucket.give_back();
+            TrySendError::Disconnected(t)
+        })
+    }
+}
+
+impl<S> Throttled<S> {
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+struct TokenBucket {
+    tokens: f64,
+    burst: f64,
+    rate: f64,     // Tokens per second
+    last: Instant, // When `tokens` was last brought up to date
+}
+
+impl TokenBucket {
+    // Takes a token, or says how long until there is one. The caller passes the time so the tests
+    // do not depend on how fast they run.
+    fn take(&mut self, now: Instant) -> Result<(), Duration> {
+        let refill = now.duration_since(self.last).as_secs_f64() * self.rate;
+        self.tokens = (self.tokens + refill).min(self.burst);
+        self.last = now;
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            Ok(())
+        } else {
+            // A tiny rate can mean a wait longer than a Duration holds
+            let wait = (1.0 - self.tokens) / self.rate;
+            Err(Duration::try_from_secs_f64(wait).unwrap_or(Duration::MAX))
+        }
+    }
+
+    fn give_back(&mut self) {
+        self.tokens = (self.tokens + 1.0).min(self.burst);
+    }
+}
+
+/// Returned by `Throttled::try_send`. Holds the value that could not be sent.
+#[derive(PartialEq, Eq, Clone, Copy)]
+pub enum TrySendError<T> {
+    WouldBlock(T),   // Out of tokens, try again later
+    Disconnected(T), // The Receiver is gone
+}
+
+impl<T> TrySendError<T> {
+    pub fn into_inner(self) -> T {
+        match self {
+            TrySendError::WouldBlock(t) | TrySendError::Disconnected(t) => t,
+        }
+    }
+}
+
+impl<T> fmt::Debug for TrySendError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TrySendError::WouldBlock(_) => f.write_str("WouldBlock(..)"),
+            TrySendError::Disconnected(_) => f.write_str("Disconnected(..)"),
+        }
+    }
+}
+
+impl<T> fmt::Display for TrySendError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TrySendError::WouldBlock(_) => f.write_str("sending would exceed the rate limit"),
+            TrySendError::Disconnected(_) => f.write_str("sending on a channel with no receiver"),
+        }
+    }
+}
+
+impl<T> std::error::Error for TrySendError<T> {}
+
+/// Merges bursts of values: after the first value of a burst, keeps receiving until nothing new
+/// arrived for `window` (debounce), and of all the values with the same key only the latest is
+/// handed out, in the place of the first one. A zero `window` merges what is queued right now.
+pub struct Coalesce<T, K, F> {
+    rx: Receiver<T>,
+    window: Duration,
+    key: F,
+    ready: VecDeque<T>, // The merged burst, handed out one by one
+    seen: HashMap<K, usize>,
+}
+
+impl<T, K, F> Coalesce<T, K, F>
+where
+    K: Hash + Eq,
+    F: FnMut(&T) -> K,
+{
+    pub fn new(rx: Receiver<T>, window: Duration, key: F) -> Self {
+        Coalesce {
+            rx,
+            window,
+            key,
+            ready: VecDeque::new(),
+            seen: HashMap::new(),
+        }
+    }
+
+    /// Blocks for the first value of a burst and then for the rest of it.
+    /// `None` once every Sender is gone and everything was handed out.
+    pub fn recv(&mut self) -> Option<T> {
+        if let Some(t) = self.ready.pop_front() {
+            return Some(t);
+        }
+        let first = self.rx.recv()?;
+        self.merge(first);
+        // Until it is quiet for a whole window. Or disconnected: then we hand out what we have and
+        // the next call finds out the channel is closed.
+        while let Ok(t) = self.rx.recv_timeout(self.window) {
+            self.merge(t);
+        }
+        self.seen.clear();
+        self.ready.pop_front()
+    }
+
+    fn merge(&mut self, t: T) {
+        match self.seen.entry((self.key)(&t)) {
+            Entry::Occupied(slot) => self.ready[*slot.get()] = t,
+            Entry::Vacant(slot) => {
+                slot.insert(self.ready.len());
+                self.ready.push_back(t);
+            }
+        }
+    }
+
+    pub fn into_inner(self) -> Receiver<T> {
+        self.rx
+    }
+}
+
+impl<T, K, F> Iterator for Coalesce<T, K, F>
+where
+    K: Hash + Eq,
+    F: FnMut(&T) -> K,
+{
+    type Item = T;
+    fn next(&mut self) -> Option<Self::Item> {
+        self.recv()
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use super::*;
+    use crate::channel;
+
+    #[test]
+    fn burst_then_would_block() {
+        let (tx, rx) = channel();
+        // Slow enough that no token comes back while the test runs
+        let mut tx = Throttled::new(tx, 0.001, 3);
+        for i in 0..3 {
+            tx.try_send(i).unwrap();
+        }
+        assert_eq!(tx.try_send(3), Err(TrySendError::WouldBlock(3)));
+        drop(tx);
+        assert_eq!(rx.collect::<Vec<_>>(), vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn refills_at_the_rate() {
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+        let mut bucket = TokenBucket {
+            tokens: 3.0,
+            burst: 3.0,
+            rate: 20.0, // One token every 50ms
+            last: start,
+        };
+        for _ in 0..3 {
+            assert_eq!(bucket.take(start), Ok(()));
+        }
+        assert_eq!(bucket.take(start), Err(Duration::from_millis(50)));
+        assert_eq!(bucket.take(at(60)), Ok(()));
+        let wait = bucket.take(at(60)).unwrap_err();
+        assert!(wait > Duration::from_millis(39) && wait < Duration::from_millis(41));
+        // Never more than `burst`, however long it was idle
+        for _ in 0..3 {
+            assert_eq!(bucket.take(at(10_000)), Ok(()));
+        }
+        assert!(bucket.take(at(10_000)).is_err());
+    }
+
+    #[test]
+    fn tiny_rate_waits_forever() {
+        let (tx, _rx) = channel();
+        let mut tx = Throttled::new(tx, 1e-30, 1);
+        tx.try_send(1).unwrap();
+        assert_eq!(tx.try_send(2), Err(TrySendError::WouldBlock(2)));
+        assert_eq!(tx.bucket.take(Instant::now()), Err(Duration::MAX));
+    }
+
+    #[test]
+    fn send_waits_for_tokens() {
+        let (tx, rx) = channel();
+        let mut tx = Throttled::new(tx, 100.0, 1);
+        let start = Instant::now();
+        for i in 0..5 {
+            tx.send(i).unwrap();
+        }
+        // The first one is free, the other four wait 10ms each
+        assert!(start.elapsed() >= Duration::from_millis(40));
+        drop(tx);
+        assert_eq!(rx.count(), 5);
+    }
+
+    #[test]
+    fn closed_rx_keeps_the_token() {
+        let (tx, rx) = channel();
+        let mut tx = Throttled::new(tx, 1.0, 1);
+        drop(rx);
+        assert_eq!(tx.try_send(1), Err(TrySendError::Disconnected(1)));
+        assert_eq!(tx.send(2), Err(SendError(2)));
+        assert_eq!(tx.bucket.take(Instant::now()), Ok(()));
+    }
+
+    #[test]
+    fn coalesce_keeps_latest_per_key() {
+        let (mut tx, rx) = channel();
+        for event in [("a", 1), ("b", 1), ("a", 2), ("c", 1), ("b", 2)].iter() {
+            tx.send(*event).unwrap();
+        }
+        drop(tx);
+        let merged: Vec<_> = Coalesce::new(rx, Duration::ZERO, |e: &(&str, i32)| e.0).collect();
+        assert_eq!(merged, vec![("a", 2), ("b", 2), ("c", 1)]);
+    }
+
+    #[test]
+    fn debounce_splits_bursts() {
+        let (mut tx, rx) = channel();
+        let t = thread::spawn(move || {
+            for burst in 0..2 {
+                for i in 0..5 {
+                    tx.send((burst, i)).unwrap();
+                    thread::sleep(Duration::from_millis(2));
+                }
+                // Quiet for longer than the window
+                thread::sleep(Duration::from_millis(200));
+            }
+        });
+        let mut rx = Coalesce::new(rx, Duration::from_millis(100), |e: &(i32, i32)| e.0);
+        assert_eq!(rx.recv(), Some((0, 4)));
+        assert_eq!(rx.recv(), Some((1, 4)));
+        assert_eq!(rx.recv(), None);
+        t.join().unwrap();
+    }
+}
@@ -0,0 +1,249 @@
+// Recording what went through a channel, to debug ordering problems after the fact.
+// Built with the `tracing` feature, every channel keeps a ring log of its last sends and receives
+// (the oldest events are dropped once the log is full). A channel made with `trace::channel` also
+// keeps a copy of every value sent, and `replay` can feed those into a fresh Receiver in the
+// recorded order, so the consumer side of a run can be reproduced without its producers.
+use crate::{Receiver, Sender};
+use std::collections::VecDeque;
+use std::fmt;
+use std::thread::{self, ThreadId};
+use std::time::Instant;
+
+/// How many events a channel keeps when it was not given a size.
+pub const DEFAULT_LOG_CAPACITY: usize = 1024;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Op {
+    Send,
+    Recv, // A Receiver handed the value out
+}
+
+#[derive(Debug, Clone)]
+pub struct Event<T> {
+    /// Position of the value in send order. The `Recv` of a value has the same `seq` as its `Send`.
+    pub seq: u64,
+    pub op: Op,
+    pub thread: ThreadId,
+    pub at: Instant,
+    /// A copy of the value, for `Send` events of a channel made with `trace::channel`.
+    pub value: Option<T>,
+}
+
+pub(crate) struct Log<T> {
+    events: VecDeque<Event<T>>,
+    capacity: usize,
+    copy: Option<fn(&T) -> T>, // `Clone::clone`, when the channel records values
+}
+
+impl<T> Log<T> {
+    pub(crate) fn new(capacity: usize, copy: Option<fn(&T) -> T>) -> Self {
+        Log {
+            events: VecDeque::with_capacity(capacity.min(DEFAULT_LOG_CAPACITY)),
+            capacity,
+            copy,
+        }
+    }
+
+    pub(crate) fn record(&mut self, op: Op, seq: u64, value: Option<&T>) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.events.len() == self.capacity {
+            self.events.pop_front();
+        }
+        let value = match (self.copy, value) {
+            (Some(copy), Some(value)) => Some(copy(value)),
+            _ => None,
+        };
+        self.events.push_back(Event {
+            seq,
+            op,
+            thread: thread::current().id(),
+            at: Instant::now(),
+            value,
+        });
+    }
+
+    pub(crate) fn drain(&mut self) -> Vec<Event<T>> {
+        self.events.drain(..).collect()
+    }
+}
+
+/// Like `crate::channel`, but the log keeps a copy of every value sent so it can be replayed.
+pub fn channel<T: Clone>(log_capacity: usize) -> (Sender<T>, Receiver<T>) {
+    with_log(crate::channel(), log_capacity)
+}
+
+/// Like `crate::sync_channel`, but the log keeps a copy of every value sent so it can be replayed.
+pub fn sync_channel<T: Clone>(capacity: usize, log_capacity: usize) -> (Sender<T>, Receiver<T>) {
+    with_log(crate::sync_channel(capacity), log_capacity)
+}
+
+fn with_log<T: Clone>(
+    (tx, rx): (Sender<T>, Receiver<T>),
+    log_capacity: usize,
+) -> (Sender<T>, Receiver<T>) {
+    tx.shared.lock().log = Log::new(log_capacity, Some(T::clone));
+    (tx, rx)
+}
+
+/// A Receiver that yields the recorded values in the order they were sent, then disconnects.
+/// Only `Send` events count, and each value only once, however the events are ordered.
+pub fn replay<T>(events: impl IntoIterator<Item = Event<T>>) -> Result<Receiver<T>, ReplayError> {
+    let mut sends: Vec<_> = events
+        .into_iter()
+        .filter(|event| event.op == Op::Send)
+        .collect();
+    sends.sort_by_key(|event| event.seq);
+    sends.dedup_by_key(|event| event.seq);
+
+    let (mut tx, rx) = crate::channel();
+    for event in sends {
+        match event.value {
+            Some(t) => tx.send(t).expect("we hold the Receiver"),
+            None => return Err(ReplayError::MissingValue(event.seq)),
+        }
+    }
+    Ok(rx)
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum ReplayError {
+    // A Send event without its value, the channel was not made with `trace::channel`
+    MissingValue(u64),
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReplayError::MissingValue(seq) => {
+                write!(f, "send event {} was recorded without its value", seq)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ReplayError {}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn records_sends_and_recvs() {
+        let (mut tx, mut rx) = channel(16);
+        tx.send("a").unwrap();
+        tx.send("b").unwrap();
+        // Takes both values out of the queue, but only hands out the first
+        assert_eq!(rx.recv(), Some("a"));
+        let events = rx.take_trace();
+        let ops: Vec<_> = events.iter().map(|e| (e.op, e.seq, e.value)).collect();
+        assert_eq!(
+            ops,
+            vec![
+                (Op::Send, 0, Some("a")),
+                (Op::Send, 1, Some("b")),
+                (Op::Recv, 0, None),
+            ]
+        );
+        assert!(events.iter().all(|e| e.thread == thread::current().id()));
+        assert!(events.windows(2).all(|w| w[0].at <= w[1].at));
+        // The log was drained
+        assert!(tx.take_trace().is_empty());
+
+        thread::sleep(std::time::Duration::from_millis(10));
+        assert_eq!(rx.recv(), Some("b"));
+        let later = rx.take_trace();
+        assert_eq!(later.len(), 1);
+        assert_eq!((later[0].op, later[0].seq), (Op::Recv, 1));
+        assert!(later[0].at >= events[2].at + std::time::Duration::from_millis(10));
+    }
+
+    #[test]
+    fn recv_many_and_try_recv_record_on_hand_out() {
+        let (mut tx, mut rx) = crate::channel();
+        for i in 0..5 {
+            tx.send(i).unwrap();
+        }
+        assert_eq!(rx.try_recv(), Ok(0)); // 1 to 4 are in the buffer now
+        let mut out = Vec::new();
+        assert_eq!(rx.recv_many(&mut out, 2), 2);
+        tx.send(5).unwrap();
+        assert_eq!(rx.recv_many(&mut out, 10), 3);
+        let recvs: Vec<_> = rx
+            .take_trace()
+            .into_iter()
+            .filter(|e| e.op == Op::Recv)
+            .map(|e| e.seq)
+            .collect();
+        assert_eq!(recvs, vec![0, 1, 2, 3, 4, 5]);
+    }
+
+    #[test]
+    fn handed_back_values_keep_their_seq() {
+        let (mut tx, mut rx) = channel(16);
+        for i in 0..4 {
+            tx.send(i).unwrap();
+        }
+        assert_eq!(rx.recv(), Some(0)); // 1 to 3 are in its buffer now
+        let mut clone = rx.clone();
+        tx.send(4).unwrap();
+        assert_eq!(clone.recv(), Some(4));
+        drop(rx); // Hands 1 to 3 back to the queue
+        assert_eq!(clone.recv(), Some(1));
+        let recvs: Vec<_> = clone
+            .take_trace()
+            .into_iter()
+            .filter(|e| e.op == Op::Recv)
+            .map(|e| e.seq)
+            .collect();
+        assert_eq!(recvs, vec![0, 4, 1]);
+    }
+
+    #[test]
+    fn ring_keeps_the_latest() {
+        let (mut tx, _rx) = channel(3);
+        for i in 0..10 {
+            tx.send(i).unwrap();
+        }
+        let seqs: Vec<_> = tx.take_trace().iter().map(|e| e.seq).collect();
+        assert_eq!(seqs, vec![7, 8, 9]);
+    }
+
+    #[test]
+    fn replay_in_send_order() {
+        let (tx, mut rx) = channel(64);
+        let producers: Vec<_> = (0..4)
+            .map(|p| {
+                let mut tx = tx.clone();
+                thread::spawn(move || {
+                    for i in 0..5 {
+                        tx.send(p * 10 + i).unwrap();
+                    }
+                })
+            })
+            .collect();
+        drop(tx);
+        let received: Vec<_> = rx.by_ref().collect();
+        for p in producers {
+            p.join().unwrap();
+        }
+
+        let mut events = rx.take_trace();
+        events.reverse(); // Order does not matter to `replay`
+        let replayed: Vec<_> = replay(events.clone()).unwrap().collect();
+        assert_eq!(replayed, received);
+        // Deterministic: the same log gives the same run every time
+        assert_eq!(replay(events).unwrap().collect::<Vec<_>>(), received);
+    }
+
+    #[test]
+    fn replay_needs_values() {
+        let (mut tx, rx) = crate::channel();
+        tx.send(1).unwrap();
+        assert_eq!(
+            replay(rx.take_trace()).err(),
+            Some(ReplayError::MissingValue(0))
+        );
+    }
+}
@@ -0,0 +1,195 @@
+// Flattening any number of levels: `Vec<Vec<Vec<T>>>`, or a recursive enum, down to the `T`s.
+// `Flatten` knows the type of its inner iterators, but here every level can be a different type
+// (and with a recursive enum the depth is only known at runtime), so every nested level becomes a
+// boxed iterator of `Node`s. Walking them keeps a stack of those iterators instead of recursing,
+// so a very deep input cannot overflow the thread's stack.
+use std::collections::VecDeque;
+use std::iter::FusedIterator;
+
+pub enum Node<L> {
+    Leaf(L),
+    Nested(Box<dyn Iterator<Item = Node<L>>>), // Items one level further down
+}
+
+/// Says whether a value is a leaf or something with more levels inside.
+/// The nested iterators are boxed as `'static`, so the values have to own their contents.
+pub trait Deep {
+    type Leaf;
+    fn into_node(self) -> Node<Self::Leaf>;
+}
+
+macro_rules! leaf {
+    ($($t:ty),*) => {
+        $(
+            impl Deep for $t {
+                type Leaf = $t;
+                fn into_node(self) -> Node<Self::Leaf> {
+                    Node::Leaf(self)
+                }
+            }
+        )*
+    };
+}
+
+leaf!(bool, char, i8, i16, i32, i64, i128, isize, u8, u16, u32, u64, u128, usize, f32, f64);
+leaf!(String, &'static str);
+
+impl<T> Deep for Vec<T>
+where
+    T: Deep + 'static,
+{
+    type Leaf = T::Leaf;
+    fn into_node(self) -> Node<Self::Leaf> {
+        Node::Nested(Box::new(self.into_iter().map(T::into_node)))
+    }
+}
+
+impl<T> Deep for VecDeque<T>
+where
+    T: Deep + 'static,
+{
+    type Leaf = T::Leaf;
+    fn into_node(self) -> Node<Self::Leaf> {
+        Node::Nested(Box::new(self.into_iter().map(T::into_node)))
+    }
+}
+
+impl<T, const N: usize> Deep for [T; N]
+where
+    T: Deep + 'static,
+{
+    type Leaf = T::Leaf;
+    fn into_node(self) -> Node<Self::Leaf> {
+        // Not `self.into_iter()`, in edition 2018 that is the slice iterator
+        Node::Nested(Box::new(IntoIterator::into_iter(self).map(T::into_node)))
+    }
+}
+
+pub fn flatten_deep<I>(iter: I) -> FlattenDeep<<I::Item as Deep>::Leaf>
+where
+    I: IntoIterator,
+    I::IntoIter: 'static,
+    I::Item: Deep + 'static,
+{
+    FlattenDeep {
+        stack: vec![Box::new(iter.into_iter().map(Deep::into_node))],
+    }
+}
+
+pub struct FlattenDeep<L> {
+    // Depth first: the last iterator is the level we are in, the ones below it are where we continue
+    // once it is done
+    stack: Vec<Box<dyn Iterator<Item = Node<L>>>>,
+}
+
+impl<L> Iterator for FlattenDeep<L> {
+    type Item = L;
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            // Nothing left on the stack means every level is done
+            let level = self.stack.last_mut()?;
+            match level.next() {
+                Some(Node::Leaf(leaf)) => return Some(leaf),
+                Some(Node::Nested(nested)) => self.stack.push(nested), // Go one level down
+                None => {
+                    self.stack.pop(); // Back up to where we came from
+                }
+            }
+        }
+    }
+}
+
+// Once the stack is empty it stays empty
+impl<L> FusedIterator for FlattenDeep<L> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty() {
+        assert_eq!(flatten_deep(Vec::<Vec<Vec<u8>>>::new()).count(), 0);
+        assert_eq!(
+            flatten_deep(vec![vec![Vec::<u8>::new()], vec![]]).count(),
+            0
+        );
+    }
+
+    #[test]
+    fn one() {
+        assert_eq!(flatten_deep(vec![vec![vec!["a"]]]).count(), 1);
+    }
+
+    #[test]
+    fn two_wide() {
+        assert_eq!(
+            flatten_deep(vec![vec![vec!["a"]], vec![vec!["b"]]]).count(),
+            2
+        );
+    }
+
+    #[test]
+    fn depth_first_order() {
+        let nested = vec![
+            vec![vec![1, 2], vec![]],
+            vec![vec![3]],
+            vec![vec![4, 5], vec![6]],
+        ];
+        assert_eq!(
+            flatten_deep(nested).collect::<Vec<_>>(),
+            vec![1, 2, 3, 4, 5, 6]
+        );
+        let arrays = [[[1, 2], [3, 4]], [[5, 6], [7, 8]]];
+        assert_eq!(
+            flatten_deep(arrays).collect::<Vec<_>>(),
+            (1..=8).collect::<Vec<_>>()
+        );
+    }
+
+    // A tree whose depth is only known at runtime
+    enum Config {
+        Value(String),
+        Group(Vec<Config>),
+    }
+
+    impl Deep for Config {
+        type Leaf = String;
+        fn into_node(self) -> Node<String> {
+            match self {
+                Config::Value(value) => Node::Leaf(value),
+                Config::Group(group) => group.into_node(),
+            }
+        }
+    }
+
+    #[test]
+    fn recursive_enum() {
+        let value = |s: &str| Config::Value(s.to_string());
+        let config = vec![
+            value("a"),
+            Config::Group(vec![
+                value("b"),
+                Config::Group(vec![Config::Group(vec![value("c")])]),
+            ]),
+            Config::Group(vec![]),
+            value("d"),
+        ];
+        assert_eq!(
+            flatten_deep(config).collect::<Vec<_>>(),
+            vec!["a", "b", "c", "d"]
+        );
+    }
+
+    #[test]
+    fn very_deep() {
+        // Deep enough that walking it recursively would overflow the stack
+        let mut config = Config::Value("bottom".to_string());
+        for _ in 0..1_000_000 {
+            config = Config::Group(vec![config]);
+        }
+        let mut iter = flatten_deep(std::iter::once(config));
+        assert_eq!(iter.next(), Some("bottom".to_string()));
+        assert_eq!(iter.next(), None);
+        assert_eq!(iter.next(), None);
+    }
+}
@@ -0,0 +1,192 @@
+// Taking turns between iterators: `interleave` alternates between two, `round_robin` goes around
+// any number of them. Once an iterator runs out the others keep taking turns without it, so every
+// item of every iterator comes out exactly once.
+use std::iter::FusedIterator;
+
+/// `a1, b1, a2, b2, ..`, then the rest of whichever is longer.
+pub fn interleave<A, B>(a: A, b: B) -> Interleave<A::IntoIter, B::IntoIter>
+where
+    A: IntoIterator,
+    B: IntoIterator<Item = A::Item>,
+{
+    Interleave {
+        a: a.into_iter(),
+        b: b.into_iter(),
+        a_next: true,
+    }
+}
+
+/// One item from every iterator in turn, in the order they are in `iters`.
+pub fn round_robin<I>(iters: impl IntoIterator<Item = I>) -> RoundRobin<I::IntoIter>
+where
+    I: IntoIterator,
+{
+    RoundRobin {
+        iters: iters.into_iter().map(IntoIterator::into_iter).collect(),
+        turn: 0,
+    }
+}
+
+pub struct Interleave<A, B> {
+    a: A,
+    b: B,
+    a_next: bool, // Whose turn it is
+}
+
+impl<A, B> Iterator for Interleave<A, B>
+where
+    A: Iterator,
+    B: Iterator<Item = A::Item>,
+{
+    type Item = A::Item;
+    fn next(&mut self) -> Option<Self::Item> {
+        // Whoever's turn it is, and if they are out the other one goes instead
+        if self.a_next {
+            self.a_next = false;
+            self.a.next().or_else(|| self.b.next())
+        } else {
+            self.a_next = true;
+            self.b.next().or_else(|| self.a.next())
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let (a_low, a_high) = self.a.size_hint();
+        let (b_low, b_high) = self.b.size_hint();
+        let high = match (a_high, b_high) {
+            (Some(a), Some(b)) => a.checked_add(b),
+            _ => None,
+        };
+        (a_low.saturating_add(b_low), high)
+    }
+}
+
+impl<A, B> FusedIterator for Interleave<A, B>
+where
+    A: FusedIterator,
+    B: FusedIterator<Item = A::Item>,
+{
+}
+
+pub struct RoundRobin<I> {
+    iters: Vec<I>, // Only the ones that did not run out yet
+    turn: usize,   // Index into `iters` of the one that goes next
+}
+
+impl<I> Iterator for RoundRobin<I>
+where
+    I: Iterator,
+{
+    type Item = I::Item;
+    fn next(&mut self) -> Option<Self::Item> {
+        while !self.iters.is_empty() {
+            if self.turn >= self.iters.len() {
+                self.turn = 0; // Back to the first one
+            }
+            match self.iters[self.turn].next() {
+                Some(i) => {
+                    self.turn += 1;
+                    return Some(i);
+                }
+                // Drop it, the one after it moves into its index and goes now
+                None => {
+                    self.iters.remove(self.turn);
+                }
+            }
+        }
+        None
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iters.iter().fold((0, Some(0)), |(low, high), iter| {
+            let (l, h) = iter.size_hint();
+            let high = match (high, h) {
+                (Some(high), Some(h)) => high.checked_add(h),
+                _ => None,
+            };
+            (low.saturating_add(l), high)
+        })
+    }
+}
+
+// An empty `iters` stays empty
+impl<I> FusedIterator for RoundRobin<I> where I: Iterator {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty() {
+        assert_eq!(interleave(Vec::<()>::new(), Vec::new()).count(), 0);
+        assert_eq!(round_robin(Vec::<Vec<()>>::new()).count(), 0);
+        assert_eq!(round_robin(vec![Vec::<()>::new(), vec![]]).count(), 0);
+    }
+
+    #[test]
+    fn one() {
+        assert_eq!(interleave(vec!["a"], vec![]).collect::<Vec<_>>(), vec!["a"]);
+        assert_eq!(interleave(vec![], vec!["b"]).collect::<Vec<_>>(), vec!["b"]);
+        assert_eq!(round_robin(vec![vec!["a"]]).collect::<Vec<_>>(), vec!["a"]);
+    }
+
+    #[test]
+    fn two_wide() {
+        assert_eq!(
+            interleave(vec!["a"], vec!["b"]).collect::<Vec<_>>(),
+            vec!["a", "b"]
+        );
+        assert_eq!(
+            round_robin(vec![vec!["a"], vec!["b"]]).collect::<Vec<_>>(),
+            vec!["a", "b"]
+        );
+    }
+
+    #[test]
+    fn uneven() {
+        assert_eq!(
+            interleave(vec![1, 3, 5, 6, 7], vec![2, 4]).collect::<Vec<_>>(),
+            vec![1, 2, 3, 4, 5, 6, 7]
+        );
+        assert_eq!(
+            interleave(vec![1], vec![2, 3, 4]).collect::<Vec<_>>(),
+            vec![1, 2, 3, 4]
+        );
+        let iters = vec![vec![1, 4, 7, 9], vec![], vec![2, 5], vec![3, 6, 8]];
+        assert_eq!(
+            round_robin(iters).collect::<Vec<_>>(),
+            (1..=9).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn lazy() {
+        // Endless iterators are fine as long as we only take what we need
+        let evens = (0..).step_by(2);
+        let odds = (1..).step_by(2);
+        assert_eq!(
+            interleave(evens, odds).take(5).collect::<Vec<_>>(),
+            vec![0, 1, 2, 3, 4]
+        );
+        let tens = vec![0.., 10.., 20..];
+        assert_eq!(
+            round_robin(tens).take(6).collect::<Vec<_>>(),
+            vec![0, 10, 20, 1, 11, 21]
+        );
+    }
+
+    #[test]
+    fn size_hint() {
+        let mut iter = interleave(vec![1, 2, 3], vec![4]);
+        assert_eq!(iter.size_hint(), (4, Some(4)));
+        iter.next();
+        assert_eq!(iter.size_hint(), (3, Some(3)));
+        assert_eq!(interleave(0..1, 0..).size_hint(), (usize::MAX, None));
+
+        let mut iter = round_robin(vec![vec![1, 2], vec![], vec![3]]);
+        assert_eq!(iter.size_hint(), (3, Some(3)));
+        iter.next();
+        assert_eq!(iter.size_hint(), (2, Some(2)));
+        assert_eq!(iter.count(), 2);
+    }
+}
@@ -1,3 +1,153 @@
+mod deep;
+mod interleave;
+mod try_flatten;
+mod windows;
+
+pub use deep::{flatten_deep, Deep, FlattenDeep, Node};
+pub use interleave::{interleave, round_robin, Interleave, RoundRobin};
+pub use try_flatten::{flatten_ok, try_flatten, TryFlatten};
+pub use windows::{chunks, windows, Chunks, Windows};
+
+use std::iter::{FusedIterator, Map};
+
+// The adapters as methods, so they chain like the ones in std: `iter.map(..).our_flatten().filter(..)`.
+// Blanket implemented below for every Iterator, importing the trait is enough.
+pub trait IteratorExt: Iterator {
+    fn our_flatten(self) -> Flatten<Self>
+    where
+        Self: Sized,
+        Self::Item: IntoIterator;
+
+    /// `our_flatten` for fixed-size arrays, which knows its exact length up front.
+    fn flatten_arrays<T, const N: usize>(self) -> FlattenArrays<Self>
+    where
+        Self: Sized + Iterator<Item = [T; N]>;
+
+    /// Like `Iterator::flat_map`: `f` turns every item into something to iterate over.
+    fn flat_map_with<F, U>(self, f: F) -> Flatten<Map<Self, F>>
+    where
+        Self: Sized,
+        F: FnMut(Self::Item) -> U,
+        U: IntoIterator;
+
+    /// Flattens every level of nesting, see `Deep`.
+    fn flatten_deep(self) -> FlattenDeep<<Self::Item as Deep>::Leaf>
+    where
+        Self: Sized + 'static,
+        Self::Item: Deep + 'static;
+
+    /// Flattens the Ok values of `Result<impl IntoIterator, E>` items, ends after the first Err.
+    fn try_flatten<U, E>(self) -> TryFlatten<Self, U, E>
+    where
+        Self: Sized + Iterator<Item = Result<U, E>>,
+        U: IntoIterator;
+
+    /// Like `try_flatten`, but keeps going after an Err.
+    fn flatten_ok<U, E>(self) -> TryFlatten<Self, U, E>
+    where
+        Self: Sized + Iterator<Item = Result<U, E>>,
+        U: IntoIterator;
+
+    /// Overlapping runs of `size` items, like `slice::windows`.
+    fn windows(self, size: usize) -> Windows<Self>
+    where
+        Self: Sized,
+        Self::Item: Clone;
+
+    /// `size` items at a time, like `slice::chunks`.
+    fn chunks(self, size: usize) -> Chunks<Self>
+    where
+        Self: Sized;
+
+    /// Takes turns with `other`, one item each.
+    fn interleave<B>(self, other: B) -> Interleave<Self, B::IntoIter>
+    where
+        Self: Sized,
+        B: IntoIterator<Item = Self::Item>;
+
+    /// Takes turns between the iterators this one yields.
+    fn round_robin(self) -> RoundRobin<<Self::Item as IntoIterator>::IntoIter>
+    where
+        Self: Sized,
+        Self::Item: IntoIterator;
+}
+
+impl<T> IteratorExt for T
+where
+    T: Iterator,
+{
+    fn our_flatten(self) -> Flatten<Self>
+    where
+        Self::Item: IntoIterator,
+    {
+        flatten(self)
+    }
+
+    fn flatten_arrays<U, const N: usize>(self) -> FlattenArrays<Self>
+    where
+        Self: Iterator<Item = [U; N]>,
+    {
+        flatten_arrays(self)
+    }
+
+    fn flat_map_with<F, U>(self, f: F) -> Flatten<Map<Self, F>>
+    where
+        F: FnMut(Self::Item) -> U,
+        U: IntoIterator,
+    {
+        flatten(self.map(f))
+    }
+
+    fn flatten_deep(self) -> FlattenDeep<<Self::Item as Deep>::Leaf>
+    where
+        Self: 'static,
+        Self::Item: Deep + 'static,
+    {
+        flatten_deep(self)
+    }
+
+    fn try_flatten<U, E>(self) -> TryFlatten<Self, U, E>
+    where
+        Self: Iterator<Item = Result<U, E>>,
+        U: IntoIterator,
+    {
+        try_flatten(self)
+    }
+
+    fn flatten_ok<U, E>(self) -> TryFlatten<Self, U, E>
+    where
+        Self: Iterator<Item = Result<U, E>>,
+        U: IntoIterator,
+    {
+        flatten_ok(self)
+    }
+
+    fn windows(self, size: usize) -> Windows<Self>
+    where
+        Self::Item: Clone,
+    {
+        windows(self, size)
+    }
+
+    fn chunks(self, size: usize) -> Chunks<Self> {
+        chunks(self, size)
+    }
+
+    fn interleave<B>(self, other: B) -> Interleave<Self, B::IntoIter>
+    where
+        B: IntoIterator<Item = Self::Item>,
+    {
+        interleave(self, other)
+    }
+
+    fn round_robin(self) -> RoundRobin<<Self::Item as IntoIterator>::IntoIter>
+    where
+        Self::Item: IntoIterator,
+    {
+        round_robin(self)
+    }
+}
+
 pub fn flatten<I>(iter: I) -> Flatten<I>
 where
     I: Iterator,
@@ -12,7 +162,10 @@ where
     O::Item: IntoIterator, // O::Item implements IntoIterator (so we can iterate over them)
 {
     outer: O,
-    inner: Option<<O::Item as IntoIterator>::IntoIter>,
+    // The inner iterator `next` is working through, and the one `next_back` is working through.
+    // They are separate so both ends can be in the middle of a different inner iterator.
+    front_iter: Option<<O::Item as IntoIterator>::IntoIter>,
+    back_iter: Option<<O::Item as IntoIterator>::IntoIter>,
 }
 
 impl<O> Flatten<O>
@@ -23,7 +176,8 @@ where
     fn new(iter: O) -> Self {
         Flatten {
             outer: iter,
-            inner: None,
+            front_iter: None,
+            back_iter: None,
         }
     }
 }
@@ -38,21 +192,150 @@ where
 
         loop {
             // Get inner iterator if it's not None
-            if let Some(ref mut inner_iter) = self.inner {
+            if let Some(ref mut inner_iter) = self.front_iter {
                 // Get next item `i` from the `inner_iter`
                 if let Some(i) = inner_iter.next() {
                     return Some(i);
                 }
-                // If `i` is none set self.inner to None
-                self.inner = None;
+                // If `i` is none set self.front_iter to None
+                self.front_iter = None;
+            }
+            // If `self.front_iter` is None get next iterator
+            if let Some(next_inner) = self.outer.next() {
+                self.front_iter = Some(next_inner.into_iter());
+            } else {
+                // The outer iterator is done, but `next_back` may have started on the last inner
+                // iterator: what is left of it comes next (this is where the two ends meet)
+                let i = self.back_iter.as_mut()?.next();
+                if i.is_none() {
+                    // Inner iterators are not necessarily fused, never ask this one again
+                    self.back_iter = None;
+                }
+                return i;
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        // What the inner iterators we already started on still have is all we know for sure
+        let (front_low, front_high) = self
+            .front_iter
+            .as_ref()
+            .map_or((0, Some(0)), |i| i.size_hint());
+        let (back_low, back_high) = self
+            .back_iter
+            .as_ref()
+            .map_or((0, Some(0)), |i| i.size_hint());
+        let low = front_low.saturating_add(back_low);
+        // Every inner iterator still in `outer` could be any length, unless there are none left.
+        // std also knows the upper bound when the inner iterators are fixed-size arrays, but that
+        // needs specialization (on the item type) which is not available on stable Rust. For
+        // arrays there is `FlattenArrays` instead.
+        let high = match (self.outer.size_hint(), front_high, back_high) {
+            ((0, Some(0)), Some(front_high), Some(back_high)) => front_high.checked_add(back_high),
+            _ => None,
+        };
+        (low, high)
+    }
+}
+
+// Once `outer` keeps returning None both inner slots get emptied, after that we keep returning None too
+impl<O> FusedIterator for Flatten<O>
+where
+    O: FusedIterator,
+    O::Item: IntoIterator,
+{
+}
+
+impl<O> DoubleEndedIterator for Flatten<O>
+where
+    O: DoubleEndedIterator,
+    O::Item: IntoIterator,
+    <O::Item as IntoIterator>::IntoIter: DoubleEndedIterator, // We take items from the back of the inner iterators too
+{
+    // The same as `next` with front and back swapped
+    fn next_back(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(ref mut inner_iter) = self.back_iter {
+                if let Some(i) = inner_iter.next_back() {
+                    return Some(i);
+                }
+                self.back_iter = None;
+            }
+            if let Some(next_inner) = self.outer.next_back() {
+                self.back_iter = Some(next_inner.into_iter());
+            } else {
+                let i = self.front_iter.as_mut()?.next_back();
+                if i.is_none() {
+                    self.front_iter = None;
+                }
+                return i;
             }
-            // If `self.inner` is None get next iterator
-            let next_inner_item = self.outer.next()?.into_iter(); // Some or None
-            self.inner = Some(next_inner_item);
         }
     }
 }
 
+pub fn flatten_arrays<I, T, const N: usize>(iter: I) -> FlattenArrays<I>
+where
+    I: Iterator<Item = [T; N]>,
+{
+    FlattenArrays {
+        inner: flatten(iter),
+    }
+}
+
+// `Flatten` where every inner iterator is an array, so every item still in `outer` is exactly `N`
+// more. That makes the size hint exact whenever the one of `outer` is, not only at the end.
+pub struct FlattenArrays<O>
+where
+    O: Iterator,
+    O::Item: IntoIterator,
+{
+    inner: Flatten<O>,
+}
+
+impl<O, T, const N: usize> Iterator for FlattenArrays<O>
+where
+    O: Iterator<Item = [T; N]>,
+{
+    type Item = T;
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let Flatten {
+            outer,
+            front_iter,
+            back_iter,
+        } = &self.inner;
+        // Started arrays know what they have left, the rest have all `N`
+        let started =
+            front_iter.as_ref().map_or(0, |i| i.len()) + back_iter.as_ref().map_or(0, |i| i.len());
+        let (low, high) = outer.size_hint();
+        (
+            low.saturating_mul(N).saturating_add(started),
+            high.and_then(|h| h.checked_mul(N)?.checked_add(started)),
+        )
+    }
+}
+
+impl<O, T, const N: usize> DoubleEndedIterator for FlattenArrays<O>
+where
+    O: DoubleEndedIterator<Item = [T; N]>,
+{
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.inner.next_back()
+    }
+}
+
+impl<O, T, const N: usize> ExactSizeIterator for FlattenArrays<O> where
+    O: ExactSizeIterator<Item = [T; N]>
+{
+}
+
+impl<O, T, const N: usize> FusedIterator for FlattenArrays<O> where O: FusedIterator<Item = [T; N]> {}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -75,4 +358,262 @@ mod tests {
     fn two_wide() {
         assert_eq!(flatten(vec![vec!["a"], vec!["b"]].into_iter()).count(), 2);
     }
+
+    #[test]
+    fn reverse() {
+        assert_eq!(
+            flatten(std::iter::once(vec!["a", "b"]))
+                .rev()
+                .collect::<Vec<_>>(),
+            vec!["b", "a"]
+        );
+    }
+
+    #[test]
+    fn reverse_wide() {
+        assert_eq!(
+            flatten(vec![vec!["a"], vec!["b"]].into_iter())
+                .rev()
+                .collect::<Vec<_>>(),
+            vec!["b", "a"]
+        );
+    }
+
+    #[test]
+    fn both_ends() {
+        let mut iter = flatten(vec![vec!["a1", "a2", "a3"], vec!["b1", "b2", "b3"]].into_iter());
+        assert_eq!(iter.next(), Some("a1"));
+        assert_eq!(iter.next_back(), Some("b3"));
+        assert_eq!(iter.next(), Some("a2"));
+        assert_eq!(iter.next_back(), Some("b2"));
+        assert_eq!(iter.next(), Some("a3"));
+        assert_eq!(iter.next_back(), Some("b1"));
+        assert_eq!(iter.next(), None);
+        assert_eq!(iter.next_back(), None);
+    }
+
+    #[test]
+    fn meet_in_the_middle() {
+        // Both ends in the same (only) inner iterator
+        let mut iter = flatten(std::iter::once(vec!["a", "b", "c"]));
+        assert_eq!(iter.next(), Some("a"));
+        assert_eq!(iter.next_back(), Some("c"));
+        assert_eq!(iter.next(), Some("b"));
+        assert_eq!(iter.next_back(), None);
+        assert_eq!(iter.next(), None);
+
+        // `next_back` started on the last inner iterator, `next` finishes it
+        let mut iter = flatten(vec![vec!["a"], vec!["b", "c", "d"]].into_iter());
+        assert_eq!(iter.next_back(), Some("d"));
+        assert_eq!(iter.next(), Some("a"));
+        assert_eq!(iter.next(), Some("b"));
+        assert_eq!(iter.next(), Some("c"));
+        assert_eq!(iter.next(), None);
+        assert_eq!(iter.next_back(), None);
+    }
+
+    #[test]
+    fn empty_inner_on_both_ends() {
+        let nested = vec![vec![], vec!["a"], vec![], vec![], vec!["b"], vec![]];
+        let mut iter = flatten(nested.into_iter());
+        assert_eq!(iter.next_back(), Some("b"));
+        assert_eq!(iter.next(), Some("a"));
+        assert_eq!(iter.next_back(), None);
+        assert_eq!(iter.next(), None);
+
+        let nested: Vec<Vec<()>> = vec![vec![], vec![], vec![]];
+        let mut iter = flatten(nested.into_iter());
+        assert_eq!(iter.next_back(), None);
+        assert_eq!(iter.next(), None);
+    }
+
+    #[test]
+    fn ext() {
+        assert_eq!(vec![vec![0, 1]].into_iter().our_flatten().count(), 2);
+    }
+
+    #[test]
+    fn ext_chains_with_std() {
+        let evens: Vec<_> = vec!["1 2", "", "3 4 5"]
+            .into_iter()
+            .map(|line| line.split(' '))
+            .our_flatten()
+            .filter(|word| !word.is_empty())
+            .map(|word| word.parse::<i32>().unwrap())
+            .filter(|n| n % 2 == 0)
+            .collect();
+        assert_eq!(evens, vec![2, 4]);
+    }
+
+    #[test]
+    fn flat_map_with() {
+        let repeated: Vec<_> = (1..4).flat_map_with(|n| vec![n; n]).collect();
+        assert_eq!(repeated, vec![1, 2, 2, 3, 3, 3]);
+        let reversed: Vec<_> = (1..3).flat_map_with(|n| 0..n).rev().collect();
+        assert_eq!(reversed, vec![1, 0, 0]);
+    }
+
+    #[test]
+    fn ext_flatten_deep() {
+        let nested = vec![vec![vec![1], vec![2, 3]], vec![], vec![vec![4]]];
+        let doubled: Vec<_> = nested.into_iter().flatten_deep().map(|n| n * 2).collect();
+        assert_eq!(doubled, vec![2, 4, 6, 8]);
+    }
+
+    #[test]
+    fn size_hint() {
+        let mut iter = flatten(vec![vec![1, 2, 3], vec![4, 5]].into_iter());
+        // Nothing started yet and more inner iterators to come
+        assert_eq!(iter.size_hint(), (0, None));
+        iter.next();
+        assert_eq!(iter.size_hint(), (2, None));
+        iter.next_back();
+        // Both inner iterators taken out of `outer`, so now we know exactly
+        assert_eq!(iter.size_hint(), (3, Some(3)));
+        assert_eq!(iter.by_ref().count(), 3);
+        assert_eq!(iter.size_hint(), (0, Some(0)));
+
+        assert_eq!(
+            flatten(std::iter::empty::<Vec<()>>()).size_hint(),
+            (0, Some(0))
+        );
+    }
+
+    #[test]
+    fn size_hint_matches_len() {
+        // The inner iterators know their exact length, so once `outer` is empty so do we
+        let nested = vec![vec![1, 2], vec![], vec![3], vec![4, 5, 6]];
+        let total = nested.iter().map(Vec::len).sum::<usize>();
+        let mut iter = flatten(nested.into_iter());
+        let mut taken = 0;
+        while iter.next().is_some() {
+            taken += 1;
+            let (low, high) = iter.size_hint();
+            assert!(low <= total - taken);
+            if let Some(high) = high {
+                assert_eq!((low, high), (total - taken, total - taken));
+            }
+        }
+        assert_eq!(taken, total);
+    }
+
+    #[test]
+    fn collect_preallocates() {
+        // `collect` allocates for the lower bound up front
+        let mut iter = flatten(std::iter::once(vec![0u8; 100]));
+        iter.next();
+        assert_eq!(iter.size_hint(), (99, Some(99)));
+        assert_eq!(iter.count(), 99);
+    }
+
+    #[test]
+    fn arrays_size_hint() {
+        let arrays = vec![[1, 2, 3], [4, 5, 6], [7, 8, 9]];
+        // Exact from the start, with nothing taken out of `outer` yet
+        let mut iter = flatten_arrays(arrays.clone().into_iter());
+        assert_eq!(iter.size_hint(), (9, Some(9)));
+        assert_eq!(iter.len(), 9);
+        iter.next();
+        iter.next_back();
+        assert_eq!(iter.len(), 7);
+        assert_eq!(
+            iter.by_ref().rev().take(4).collect::<Vec<_>>(),
+            vec![8, 7, 6, 5]
+        );
+        assert_eq!(iter.len(), 3);
+        assert_eq!(iter.collect::<Vec<_>>(), vec![2, 3, 4]);
+
+        let mut iter = arrays.into_iter().flatten_arrays();
+        let mut left = 9;
+        while iter.next().is_some() {
+            left -= 1;
+            assert_eq!(iter.size_hint(), (left, Some(left)));
+        }
+        assert_eq!(left, 0);
+
+        // Unknown number of arrays, but a known minimum
+        let iter = flatten_arrays((0..).map(|i| [i; 2]));
+        assert_eq!(iter.size_hint(), (usize::MAX, None));
+        assert_eq!(flatten_arrays(std::iter::empty::<[u8; 0]>()).len(), 0);
+    }
+
+    #[test]
+    fn fused() {
+        // An inner iterator that comes back to life after returning None
+        struct Flaky(bool);
+        impl Iterator for Flaky {
+            type Item = u8;
+            fn next(&mut self) -> Option<u8> {
+                self.0 = !self.0;
+                if self.0 {
+                    Some(1)
+                } else {
+                    None
+                }
+            }
+        }
+        impl DoubleEndedIterator for Flaky {
+            fn next_back(&mut self) -> Option<u8> {
+                self.next()
+            }
+        }
+        fn assert_fused<I: FusedIterator>(_: &I) {}
+
+        let mut iter = flatten(std::iter::once(Flaky(false)).fuse());
+        assert_fused(&iter);
+        assert_eq!(iter.next_back(), Some(1));
+        assert_eq!(iter.next(), None);
+        assert_eq!(iter.next(), None);
+        assert_eq!(iter.next_back(), None);
+    }
+
+    #[test]
+    fn ext_try_flatten() {
+        let lines = ["1 2", "x", "3"];
+        let parse = |line: &str| -> Result<Vec<i32>, String> {
+            line.split(' ')
+                .map(|n| n.parse().map_err(|_| format!("not a number: {}", n)))
+                .collect()
+        };
+        let all: Vec<_> = lines.iter().map(|l| parse(l)).flatten_ok().collect();
+        assert_eq!(
+            all,
+            vec![Ok(1), Ok(2), Err("not a number: x".to_string()), Ok(3)]
+        );
+        let total: Result<i32, _> = lines.iter().map(|l| parse(l)).try_flatten().sum();
+        assert_eq!(total, Err("not a number: x".to_string()));
+    }
+
+    #[test]
+    fn ext_adapters() {
+        let moving_sums: Vec<i32> = (1..=5).windows(2).map(|w| w.iter().sum()).collect();
+        assert_eq!(moving_sums, vec![3, 5, 7, 9]);
+        let rows: Vec<_> = (1..=5).map(|n| n * 10).chunks(2).collect();
+        assert_eq!(rows, vec![vec![10, 20], vec![30, 40], vec![50]]);
+        let merged: Vec<_> = vec![1, 3].into_iter().interleave(vec![2, 4, 5]).collect();
+        assert_eq!(merged, vec![1, 2, 3, 4, 5]);
+        let fair: Vec<_> = vec![vec![1, 3], vec![2]]
+            .into_iter()
+            .round_robin()
+            .filter(|n| n % 2 == 1)
+            .collect();
+        assert_eq!(fair, vec![1, 3]);
+    }
+
+    #[test]
+    fn matches_std() {
+        let nested = vec![vec![1, 2], vec![], vec![3], vec![4, 5, 6], vec![]];
+        // Every pattern of front/back calls, as bits: 1 is `next_back`
+        for pattern in 0..1u32 << 7 {
+            let mut ours = flatten(nested.clone().into_iter());
+            let mut theirs = nested.clone().into_iter().flatten();
+            for step in 0..7 {
+                if pattern & (1 << step) != 0 {
+                    assert_eq!(ours.next_back(), theirs.next_back());
+                } else {
+                    assert_eq!(ours.next(), theirs.next());
+                }
+            }
+        }
+    }
 }
@@ -0,0 +1,178 @@
+// Flattening an iterator of `Result<impl IntoIterator, E>`, like a reader of `Result<Vec<T>, E>`.
+// The Ok values are flattened like in `Flatten`, and an Err comes out where it was, between the
+// items of the inner iterators before and after it. `try_flatten` stops after the first error
+// (like `?` would), `flatten_ok` passes every error on and keeps going.
+use std::iter::FusedIterator;
+
+pub fn try_flatten<I, U, E>(iter: I) -> TryFlatten<I::IntoIter, U, E>
+where
+    I: IntoIterator<Item = Result<U, E>>,
+    U: IntoIterator,
+{
+    TryFlatten::new(iter.into_iter(), true)
+}
+
+pub fn flatten_ok<I, U, E>(iter: I) -> TryFlatten<I::IntoIter, U, E>
+where
+    I: IntoIterator<Item = Result<U, E>>,
+    U: IntoIterator,
+{
+    TryFlatten::new(iter.into_iter(), false)
+}
+
+pub struct TryFlatten<O, U, E>
+where
+    O: Iterator<Item = Result<U, E>>,
+    U: IntoIterator,
+{
+    outer: O,
+    inner: Option<U::IntoIter>,
+    stop_after_error: bool,
+    stopped: bool, // We handed out an error and `stop_after_error` is set
+}
+
+impl<O, U, E> TryFlatten<O, U, E>
+where
+    O: Iterator<Item = Result<U, E>>,
+    U: IntoIterator,
+{
+    fn new(outer: O, stop_after_error: bool) -> Self {
+        TryFlatten {
+            outer,
+            inner: None,
+            stop_after_error,
+            stopped: false,
+        }
+    }
+}
+
+impl<O, U, E> Iterator for TryFlatten<O, U, E>
+where
+    O: Iterator<Item = Result<U, E>>,
+    U: IntoIterator,
+{
+    type Item = Result<U::Item, E>;
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.stopped {
+            return None;
+        }
+        loop {
+            if let Some(ref mut inner_iter) = self.inner {
+                if let Some(i) = inner_iter.next() {
+                    return Some(Ok(i));
+                }
+                self.inner = None;
+            }
+            // Same as `Flatten::next`, except the next inner iterator may be an error instead
+            match self.outer.next()? {
+                Ok(next_inner) => self.inner = Some(next_inner.into_iter()),
+                Err(e) => {
+                    self.stopped = self.stop_after_error;
+                    return Some(Err(e));
+                }
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        if self.stopped {
+            return (0, Some(0));
+        }
+        let (low, high) = self.inner.as_ref().map_or((0, Some(0)), |i| i.size_hint());
+        // Nothing is known about what is left in `outer`, unless nothing is
+        match self.outer.size_hint() {
+            (0, Some(0)) => (low, high),
+            _ => (low, None),
+        }
+    }
+}
+
+impl<O, U, E> FusedIterator for TryFlatten<O, U, E>
+where
+    O: FusedIterator<Item = Result<U, E>>,
+    U: IntoIterator,
+{
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty() {
+        assert_eq!(
+            try_flatten(std::iter::empty::<Result<Vec<()>, ()>>()).count(),
+            0
+        );
+        assert_eq!(flatten_ok(vec![Ok::<Vec<()>, ()>(vec![])]).count(), 0);
+    }
+
+    #[test]
+    fn one() {
+        assert_eq!(
+            try_flatten(std::iter::once(Ok::<_, ()>(vec!["a"]))).collect::<Vec<_>>(),
+            vec![Ok("a")]
+        );
+    }
+
+    #[test]
+    fn two_wide() {
+        let items = vec![Ok::<_, ()>(vec!["a"]), Ok(vec!["b"])];
+        assert_eq!(try_flatten(items).count(), 2);
+    }
+
+    #[test]
+    fn error_in_position() {
+        let items = vec![
+            Ok(vec![1, 2]),
+            Err("bad"),
+            Ok(vec![3]),
+            Err("worse"),
+            Ok(vec![4]),
+        ];
+        assert_eq!(
+            flatten_ok(items.clone()).collect::<Vec<_>>(),
+            vec![Ok(1), Ok(2), Err("bad"), Ok(3), Err("worse"), Ok(4)]
+        );
+        assert_eq!(
+            try_flatten(items).collect::<Vec<_>>(),
+            vec![Ok(1), Ok(2), Err("bad")]
+        );
+    }
+
+    #[test]
+    fn collect_result() {
+        let good = vec![Ok::<_, String>(vec![1, 2]), Ok(vec![3])];
+        assert_eq!(
+            try_flatten(good).collect::<Result<Vec<_>, _>>(),
+            Ok(vec![1, 2, 3])
+        );
+
+        let bad = vec![Ok(vec![1]), Err("no".to_string()), Ok(vec![2])];
+        assert_eq!(
+            try_flatten(bad).collect::<Result<Vec<_>, _>>(),
+            Err("no".to_string())
+        );
+    }
+
+    #[test]
+    fn stays_stopped() {
+        let mut iter = try_flatten(vec![Err(1), Ok(vec![2]), Err(3)]);
+        assert_eq!(iter.next(), Some(Err(1)));
+        assert_eq!(iter.size_hint(), (0, Some(0)));
+        assert_eq!(iter.next(), None);
+        assert_eq!(iter.next(), None);
+    }
+
+    #[test]
+    fn size_hint() {
+        let mut iter = flatten_ok(vec![Ok::<_, ()>(vec![1, 2, 3]), Ok(vec![4])]);
+        assert_eq!(iter.size_hint(), (0, None));
+        iter.next();
+        assert_eq!(iter.size_hint(), (2, None));
+        iter.next();
+        iter.next();
+        iter.next();
+        assert_eq!(iter.size_hint(), (0, Some(0)));
+    }
+}
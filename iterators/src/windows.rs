@@ -0,0 +1,213 @@
+// `slice::windows` and `slice::chunks` for any iterator.
+// A slice can hand out borrowed sub-slices, an iterator cannot (its items are gone once we move
+// on), so both yield owned `Vec`s. `Windows` keeps the last `size` items in a ring buffer
+// (`VecDeque`) and clones them into every window, `Chunks` moves the items into the Vec it yields.
+use std::collections::VecDeque;
+use std::iter::FusedIterator;
+
+/// Every run of `size` consecutive items, overlapping. Nothing if there are fewer than `size`.
+pub fn windows<I>(iter: I, size: usize) -> Windows<I::IntoIter>
+where
+    I: IntoIterator,
+    I::Item: Clone,
+{
+    assert!(size > 0, "window size must be non-zero");
+    Windows {
+        iter: iter.into_iter(),
+        size,
+        // Not `with_capacity(size)`: `size` can be far more than the iterator will ever yield.
+        // `next` fills the first window through `take`, which only reserves what is there.
+        window: VecDeque::new(),
+    }
+}
+
+/// `size` items at a time, the last chunk may be shorter.
+pub fn chunks<I>(iter: I, size: usize) -> Chunks<I::IntoIter>
+where
+    I: IntoIterator,
+{
+    assert!(size > 0, "chunk size must be non-zero");
+    Chunks {
+        iter: iter.into_iter(),
+        size,
+    }
+}
+
+pub struct Windows<I>
+where
+    I: Iterator,
+{
+    iter: I,
+    size: usize,
+    window: VecDeque<I::Item>, // The last window we yielded, empty before the first one
+}
+
+impl<I> Iterator for Windows<I>
+where
+    I: Iterator,
+    I::Item: Clone,
+{
+    type Item = Vec<I::Item>;
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.window.is_empty() {
+            // The first window needs `size` items, the later ones just one more each
+            self.window.extend(self.iter.by_ref().take(self.size));
+            if self.window.len() < self.size {
+                self.window.clear();
+                return None;
+            }
+        } else {
+            let next = self.iter.next()?;
+            // Slide by one: the oldest item falls out of the ring buffer
+            self.window.pop_front();
+            self.window.push_back(next);
+        }
+        Some(self.window.iter().cloned().collect())
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let (low, high) = self.iter.size_hint();
+        if self.window.is_empty() {
+            // The first window takes `size` items, every item after that is another window
+            let first = self.size - 1;
+            (
+                low.saturating_sub(first),
+                high.map(|h| h.saturating_sub(first)),
+            )
+        } else {
+            (low, high)
+        }
+    }
+}
+
+impl<I> FusedIterator for Windows<I>
+where
+    I: FusedIterator,
+    I::Item: Clone,
+{
+}
+
+pub struct Chunks<I> {
+    iter: I,
+    size: usize,
+}
+
+impl<I> Iterator for Chunks<I>
+where
+    I: Iterator,
+{
+    type Item = Vec<I::Item>;
+    fn next(&mut self) -> Option<Self::Item> {
+        let chunk: Vec<_> = self.iter.by_ref().take(self.size).collect();
+        if chunk.is_empty() {
+            None
+        } else {
+            Some(chunk)
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let (low, high) = self.iter.size_hint();
+        // Rounded up, the last chunk may be shorter
+        let chunks = |n: usize| n.div_ceil(self.size);
+        (chunks(low), high.map(chunks))
+    }
+}
+
+impl<I> FusedIterator for Chunks<I> where I: FusedIterator {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty() {
+        assert_eq!(windows(std::iter::empty::<()>(), 1).count(), 0);
+        assert_eq!(chunks(std::iter::empty::<()>(), 1).count(), 0);
+    }
+
+    #[test]
+    fn one() {
+        assert_eq!(windows(vec!["a"], 1).collect::<Vec<_>>(), vec![vec!["a"]]);
+        assert_eq!(chunks(vec!["a"], 2).collect::<Vec<_>>(), vec![vec!["a"]]);
+        // Not enough for a single window
+        assert_eq!(windows(vec!["a"], 2).count(), 0);
+    }
+
+    #[test]
+    fn two() {
+        assert_eq!(
+            windows(vec!["a", "b"], 2).collect::<Vec<_>>(),
+            vec![vec!["a", "b"]]
+        );
+        assert_eq!(
+            chunks(vec!["a", "b"], 1).collect::<Vec<_>>(),
+            vec![vec!["a"], vec!["b"]]
+        );
+    }
+
+    #[test]
+    fn matches_slices() {
+        let items: Vec<_> = (0..7).collect();
+        for size in 1..9 {
+            let ours: Vec<_> = windows(items.iter().copied(), size).collect();
+            let theirs: Vec<_> = items.windows(size).map(|w| w.to_vec()).collect();
+            assert_eq!(ours, theirs);
+
+            let ours: Vec<_> = chunks(items.iter().copied(), size).collect();
+            let theirs: Vec<_> = items.chunks(size).map(|c| c.to_vec()).collect();
+            assert_eq!(ours, theirs);
+        }
+    }
+
+    #[test]
+    fn lazy() {
+        // Works on an endless iterator, and only pulls what it needs
+        let mut pulled = 0;
+        let counting = std::iter::repeat_with(|| {
+            pulled += 1;
+            pulled
+        });
+        let first: Vec<_> = windows(counting, 3).take(2).collect();
+        assert_eq!(first, vec![vec![1, 2, 3], vec![2, 3, 4]]);
+        assert_eq!(pulled, 4);
+    }
+
+    #[test]
+    fn size_hint() {
+        let mut w = windows(0..5, 3);
+        assert_eq!(w.size_hint(), (3, Some(3)));
+        w.next();
+        assert_eq!(w.size_hint(), (2, Some(2)));
+        assert_eq!(windows(0..2, 3).size_hint(), (0, Some(0)));
+        assert_eq!(windows(0.., 3).size_hint(), (usize::MAX - 2, None));
+
+        let mut c = chunks(0..5, 2);
+        assert_eq!(c.size_hint(), (3, Some(3)));
+        c.next();
+        assert_eq!(c.size_hint(), (2, Some(2)));
+        assert_eq!(chunks(0..4, 2).size_hint(), (2, Some(2)));
+
+        for size in 1..5 {
+            assert_eq!(
+                windows(0..5, size).size_hint().0,
+                windows(0..5, size).count()
+            );
+            assert_eq!(chunks(0..5, size).size_hint().0, chunks(0..5, size).count());
+        }
+    }
+
+    #[test]
+    fn huge_size() {
+        assert_eq!(windows(0..3, usize::MAX).count(), 0);
+        let w = windows(0..3, 1 << 40);
+        assert_eq!(w.window.capacity(), 0);
+        assert_eq!(w.count(), 0);
+    }
+
+    #[test]
+    #[should_panic]
+    fn zero_size() {
+        windows(0..5, 0);
+    }
+}